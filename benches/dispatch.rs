@@ -0,0 +1,127 @@
+//! Compares `AwaCPU::run` with driving the same program through `AwaCPU::step` and with the
+//! dispatch loop the interpreter used before programs were lowered: matching on `Awatism` and
+//! looking up every jump target in a `HashMap`.
+//!
+//! Run with `cargo bench`.
+#![allow(clippy::needless_return)]
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use awatistic::{AwaCPU, Awatism, AwawaError, Program, StepEvent};
+
+/// Counts to 1000000 in a loop like `examples/loop.awasm`, without printing
+const COUNT_LOOP: &str = "
+blo 100
+dpl
+dpl
+mul
+mul
+blo 0
+lbl 1
+blo 1
+add
+lss
+jmp 1
+trm
+";
+
+const RUNS: u32 = 5;
+
+/// Runs the program with `run`, which returns the number of executed awatisms, and returns the
+/// best time
+fn bench(name: &str, program: &Program, run: fn(&mut AwaCPU) -> u64) -> Duration {
+    let mut best = Duration::MAX;
+    let mut steps = 0;
+    for _ in 0..RUNS {
+        let mut cpu = AwaCPU::with_io(program, 0, "".as_bytes(), vec![]);
+        let start = Instant::now();
+        steps = run(&mut cpu);
+        best = best.min(start.elapsed());
+    }
+
+    let per_step = best.as_nanos() as f64 / steps as f64;
+    println!("{name:>8}: {best:>10.2?} for {steps} awatisms, {per_step:.2} ns/awatism");
+    return best;
+}
+
+/// Runs the program by matching on its awatisms and looking up labels in a `HashMap` on every
+/// `jmp`, only using the CPU for the Bubble Abyss
+fn run_awatisms(cpu: &mut AwaCPU) -> u64 {
+    let awatisms = cpu.get_program().awatisms();
+    let mut labels = HashMap::new();
+    for (ip, awatism) in awatisms.iter().enumerate() {
+        if let Awatism::Lbl(lbl) = awatism {
+            labels.insert(*lbl, ip);
+        }
+    }
+
+    let mut ip = 0;
+    let mut steps = 0;
+    while let Some(awatism) = awatisms.get(ip) {
+        steps += 1;
+        // Comparisons skip the next awatism by advancing the CPU's instruction pointer
+        let cpu_ip = cpu.get_ip();
+        let mut next = ip + 1;
+        let res = match awatism {
+            Awatism::Nop => cpu.nop(),
+            Awatism::Prn => cpu.prn(),
+            Awatism::Pr1 => cpu.pr1(),
+            Awatism::Red => cpu.red(),
+            Awatism::R3d => cpu.r3d(),
+            Awatism::Blo(val) => cpu.blo(*val),
+            Awatism::Sbm(val) => cpu.sbm(*val),
+            Awatism::Pop => cpu.pop(),
+            Awatism::Dpl => cpu.dpl(),
+            Awatism::Srn(val) => cpu.srn(*val),
+            Awatism::Mrg => cpu.mrg(),
+            Awatism::Add => cpu.add(),
+            Awatism::Sub => cpu.sub(),
+            Awatism::Mul => cpu.mul(),
+            Awatism::Div => cpu.div(),
+            Awatism::Cnt => cpu.cnt(),
+            Awatism::Lbl(val) => cpu.lbl(*val),
+            Awatism::Jmp(val) => match labels.get(val) {
+                Some(target) => {
+                    next = *target;
+                    Ok(())
+                }
+                None => Err(AwawaError::InvalidLabelError(*val)),
+            },
+            Awatism::Eql => cpu.eql(),
+            Awatism::Lss => cpu.lss(),
+            Awatism::Gr8 => cpu.gr8(),
+            Awatism::Trm => return steps,
+        };
+        if let Err(e) = res {
+            panic!("{e}");
+        }
+        if cpu.get_ip() != cpu_ip {
+            next += 1;
+        }
+        ip = next;
+    }
+    return steps;
+}
+
+fn main() {
+    let program = Program::from_awasm("count.awasm", COUNT_LOOP.as_bytes()).unwrap();
+
+    let baseline = bench("awatisms", &program, run_awatisms);
+    bench("step", &program, |cpu| loop {
+        match cpu.step() {
+            Ok(StepEvent::Halted { .. }) => return cpu.get_steps(),
+            Ok(_) => (),
+            Err(e) => panic!("{e}"),
+        }
+    });
+    let run = bench("run", &program, |cpu| match cpu.run() {
+        Err(AwawaError::EndOfProgramError()) => return cpu.get_steps(),
+        res => panic!("unexpected result {res:?}"),
+    });
+
+    let speedup = baseline.as_secs_f64() / run.as_secs_f64();
+    println!("run is {speedup:.2}x as fast as dispatching on awatisms");
+}
@@ -1,29 +1,69 @@
 use crate::{
     awacpu::awascii::awascii,
-    errors::{AwawaLoadError, AwawaLoadResult, AwawaResult},
-    AwawaError,
+    errors::{AwawaError, AwawaResult},
+    program::{Instr, Program},
 };
+pub mod arithmetic;
 pub mod awascii;
+pub mod awatalk;
 pub mod bubblestack;
+pub mod limits;
+pub mod profile;
+pub mod trace;
 
 use std::{
-    collections::{HashMap, VecDeque},
-    fmt::Display,
-    str::Chars,
+    cmp::Ordering,
+    collections::{vec_deque::Iter, VecDeque},
+    fmt::{Display, Write as _},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    time::Instant,
 };
 
-use self::bubblestack::{BubbleItem, BubbleStack};
+use self::{
+    arithmetic::{Arithmetic, Op},
+    bubblestack::{BubbleItem, BubbleStack, Bubbles, Walk},
+    limits::{Limits, TIMEOUT_CHECK_INTERVAL},
+    profile::Profile,
+    trace::JsonTrace,
+};
 
 pub struct AwaCPU<'a> {
-    awa_it: Chars<'a>,
+    program: &'a Program,
     bubble_abyss: BubbleStack,
-    awatism_cache: Vec<Awatism>,
     ip: usize,
     verbose: u8,
-    labels: HashMap<u8, usize>,
+    arithmetic: Arithmetic,
+    limits: Limits,
+    steps: u64,
+    started: Option<Instant>,
+    /// Receives a JSON line for every step if set
+    trace: Option<JsonTrace<'a>>,
+    /// Execution counts if profiling is enabled
+    profile: Option<Profile>,
+    input: Box<dyn BufRead + 'a>,
+    /// Program output is buffered until `red`/`r3d`, the end of the program or an error
+    output: BufWriter<Box<dyn Write + 'a>>,
+}
+
+/// Describes what happened during a single call to `AwaCPU::step`
+#[derive(Clone, Debug, PartialEq)]
+pub enum StepEvent {
+    /// An awatism was executed
+    Executed {
+        awatism: Awatism,
+        ip_before: usize,
+        ip_after: usize,
+        /// Outcome of `eql`, `lss` and `gr8`, `Some(false)` means the next awatism was skipped
+        comparison: Option<bool>,
+        /// Text printed by `prn` and `pr1`
+        output: Option<String>,
+    },
+    /// The program ended, either through `trm` or by running past the last awatism
+    Halted { ip: usize },
 }
 
 #[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Awatism {
     Nop = 0x0,
     Prn = 0x1,
@@ -53,6 +93,43 @@ impl Awatism {
     pub fn discriminant(&self) -> u8 {
         unsafe { *<*const _>::from(self).cast::<u8>() }
     }
+
+    /// The awatism's mnemonic without its argument
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Self::Nop => "nop",
+            Self::Prn => "prn",
+            Self::Pr1 => "pr1",
+            Self::Red => "red",
+            Self::R3d => "r3d",
+            Self::Blo(_) => "blo",
+            Self::Sbm(_) => "sbm",
+            Self::Pop => "pop",
+            Self::Dpl => "dpl",
+            Self::Srn(_) => "srn",
+            Self::Mrg => "mrg",
+            Self::Add => "4dd",
+            Self::Sub => "sub",
+            Self::Mul => "mul",
+            Self::Div => "div",
+            Self::Cnt => "cnt",
+            Self::Lbl(_) => "lbl",
+            Self::Jmp(_) => "jmp",
+            Self::Eql => "eql",
+            Self::Lss => "lss",
+            Self::Gr8 => "gr8",
+            Self::Trm => "trm",
+        };
+    }
+
+    /// The awatism's argument, if it takes one
+    pub fn operand(&self) -> Option<i32> {
+        return match self {
+            Self::Blo(v) => Some(*v as i32),
+            Self::Sbm(v) | Self::Srn(v) | Self::Lbl(v) | Self::Jmp(v) => Some(*v as i32),
+            _ => None,
+        };
+    }
 }
 
 impl Display for Awatism {
@@ -94,173 +171,344 @@ impl Display for Awatism {
 }
 
 impl<'a> AwaCPU<'a> {
-    pub fn new(awa_it: Chars<'a>, verbose: u8) -> AwaCPU<'a> {
+    /// Creates a CPU running `program` that reads from stdin and prints to stdout.
+    pub fn new(program: &'a Program, verbose: u8) -> AwaCPU<'a> {
+        return Self::with_io(program, verbose, BufReader::new(io::stdin()), io::stdout());
+    }
+
+    /// Creates a CPU running `program` that reads `red`/`r3d` input from `input` and prints to
+    /// `output`.
+    pub fn with_io<R, W>(program: &'a Program, verbose: u8, input: R, output: W) -> AwaCPU<'a>
+    where
+        R: BufRead + 'a,
+        W: Write + 'a,
+    {
         return Self {
-            awa_it,
+            program,
             bubble_abyss: BubbleStack::default(),
-            awatism_cache: vec![],
             ip: 0,
             verbose,
-            labels: HashMap::new(),
+            arithmetic: Arithmetic::default(),
+            limits: Limits::default(),
+            steps: 0,
+            started: None,
+            trace: None,
+            profile: None,
+            input: Box::new(input),
+            output: BufWriter::new(Box::new(output)),
         };
     }
 
+    pub fn set_input<R: BufRead + 'a>(&mut self, input: R) {
+        self.input = Box::new(input);
+    }
+
+    /// Replaces the output, anything still buffered is written to the previous output first
+    pub fn set_output<W: Write + 'a>(&mut self, output: W) {
+        let _ = self.output.flush();
+        self.output = BufWriter::new(Box::new(output));
+    }
+
+    /// Writes buffered program output
+    pub fn flush(&mut self) -> AwawaResult {
+        return self.output.flush().map_err(|_| AwawaError::WriteError);
+    }
+
+    /// Writes a JSON line describing every step to `trace`, see `JsonTrace`.
+    /// `run` single-steps the program while a trace is set.
+    pub fn set_trace<W: Write + 'a>(&mut self, trace: W) {
+        self.trace = Some(JsonTrace::new(trace));
+    }
+
+    /// Counts how often each awatism is executed and the outcomes of comparisons from now on,
+    /// see `get_profile`.
+    /// `run` single-steps the program while profiling.
+    pub fn enable_profile(&mut self) {
+        self.profile = Some(Profile::new(self.program.len()));
+    }
+
+    /// Returns the execution counts if profiling is enabled
+    pub fn get_profile(&self) -> Option<&Profile> {
+        return self.profile.as_ref();
+    }
+
+    /// Selects how arithmetic treats results that do not fit an `i32`
+    pub fn set_arithmetic(&mut self, arithmetic: Arithmetic) {
+        self.arithmetic = arithmetic;
+    }
+
+    pub fn get_arithmetic(&self) -> Arithmetic {
+        return self.arithmetic;
+    }
+
+    /// Sets the resource limits checked by `step`
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn get_limits(&self) -> Limits {
+        return self.limits;
+    }
+
+    /// Number of awatisms executed so far
+    pub fn get_steps(&self) -> u64 {
+        return self.steps;
+    }
+
     pub fn get_ip(&self) -> usize {
         return self.ip;
     }
 
-    pub fn get_program(&self) -> &Vec<Awatism> {
-        return &self.awatism_cache;
+    pub fn get_program(&self) -> &'a Program {
+        return self.program;
     }
 
     pub fn get_bubble_abyss(&self) -> &BubbleStack {
         return &self.bubble_abyss;
     }
 
-    pub fn disawassemble(&mut self) -> AwawaLoadResult {
-        let preamble = read_n(&mut self.awa_it, 1).unwrap_or(Some(1)).unwrap_or(1);
-        if preamble != 0 {
-            return Err(AwawaLoadError::MissingInitialAwaError);
-        }
+    /// Returns the instruction index of label `lbl` if the program defines it
+    pub fn get_label(&self, lbl: u8) -> Option<usize> {
+        return self.program.get_label(lbl);
+    }
 
-        loop {
-            let code = match read_n(&mut self.awa_it, 5)? {
-                Some(a) => a,
-                None => return Ok(()),
-            };
+    pub fn run(&mut self) -> AwawaResult {
+        let res = if self.verbose > 0 || self.trace.is_some() || self.profile.is_some() {
+            self.run_steps()
+        } else {
+            self.run_fast()
+        };
 
-            let awatism = self.fetch_awatism(code)?;
-            println!("{awatism}");
+        // Keep the original error if flushing fails as well
+        let mut flushed = self.flush();
+        if let Some(trace) = &mut self.trace {
+            flushed = flushed.and(trace.flush());
         }
+        if let Err(AwawaError::EndOfProgramError()) = res {
+            flushed?;
+        }
+        return res;
     }
 
-    pub fn run(&mut self) -> AwawaResult {
+    fn run_steps(&mut self) -> AwawaResult {
         loop {
-            self.execute_awatism()?;
+            if let StepEvent::Halted { .. } = self.step()? {
+                return Err(AwawaError::EndOfProgramError());
+            }
         }
     }
 
-    pub fn load_program(&mut self) -> AwawaLoadResult {
-        let preamble = read_n(&mut self.awa_it, 1).unwrap_or(Some(1)).unwrap_or(1);
-        if preamble != 0 {
-            return Err(crate::errors::AwawaLoadError::MissingInitialAwaError);
-        }
-
-        let mut ip = 0;
+    fn run_fast(&mut self) -> AwawaResult {
+        let code = self.program.code();
+        let limited = self.limits != Limits::default();
         loop {
-            let code = match read_n(&mut self.awa_it, 5)? {
-                Some(a) => a,
-                None => return Ok(()),
+            let instr = match code.get(self.ip) {
+                Some(instr) => *instr,
+                None => return Err(AwawaError::EndOfProgramError()),
             };
 
-            let awatism = self.fetch_awatism(code)?;
-            match awatism {
-                Awatism::Lbl(lbl) => {
-                    self.labels.insert(lbl, ip);
-                    ()
-                }
-                _ => (),
+            if limited {
+                self.check_fuel()?;
             }
-            if self.verbose >= 3 {
-                println!("Load: [{ip}] {awatism}");
+            self.steps += 1;
+
+            let next = self.execute(instr)?;
+            if limited {
+                self.check_abyss(instr)?;
             }
-            self.awatism_cache.push(awatism);
-            ip += 1;
+            self.ip = next;
         }
     }
 
-    fn fetch_awatism(&mut self, awatism: u8) -> Result<Awatism, AwawaLoadError> {
-        return match awatism {
-            0x0 => Ok(Awatism::Nop),
-            0x1 => Ok(Awatism::Prn),
-            0x2 => Ok(Awatism::Pr1),
-            0x3 => Ok(Awatism::Red),
-            0x4 => Ok(Awatism::R3d),
-            0x5 => {
-                let val = read_n_arg(&mut self.awa_it, 8)?;
-                return Ok(Awatism::Blo(val as i8));
-            }
-            0x6 => {
-                let val = read_n_arg(&mut self.awa_it, 5)?;
-                return Ok(Awatism::Sbm(val));
-            }
-            0x7 => Ok(Awatism::Pop),
-            0x8 => Ok(Awatism::Dpl),
-            0x9 => {
-                let val = read_n_arg(&mut self.awa_it, 5)?;
-                return Ok(Awatism::Srn(val));
-            }
-            0x0A => Ok(Awatism::Mrg),
-            0x0B => Ok(Awatism::Add),
-            0x0C => Ok(Awatism::Sub),
-            0x0D => Ok(Awatism::Mul),
-            0x0E => Ok(Awatism::Div),
-            0x0F => Ok(Awatism::Cnt),
-            0x10 => {
-                let val = read_n_arg(&mut self.awa_it, 5)?;
-                return Ok(Awatism::Lbl(val));
+    /// Runs the loaded program with `input` and `output` in place of the CPU's current streams.
+    pub fn run_with_io<R, W>(&mut self, input: R, output: W) -> AwawaResult
+    where
+        R: BufRead + 'a,
+        W: Write + 'a,
+    {
+        self.set_input(input);
+        self.set_output(output);
+        return self.run();
+    }
+
+    /// Executes the awatism at the instruction pointer and advances it.
+    /// Returns `StepEvent::Halted` once the program has ended.
+    /// Output of `prn` and `pr1` is flushed right away so it interleaves with the caller's own.
+    pub fn step(&mut self) -> Result<StepEvent, AwawaError> {
+        let ip = self.ip;
+        let res = match self.profile {
+            Some(_) => self.step_profiled(),
+            None => self.step_untraced(),
+        };
+        if let Some(trace) = &mut self.trace {
+            let traced = trace.record(self.steps, &res, ip, &self.bubble_abyss);
+            // The error of the step itself matters more than a broken trace
+            if res.is_ok() {
+                traced?;
             }
-            0x11 => {
-                let val = read_n_arg(&mut self.awa_it, 5)?;
-                return Ok(Awatism::Jmp(val));
+        }
+        return res;
+    }
+
+    /// Steps and records the awatism in the profile, counting Double Bubble allocations only
+    /// here so running without a profile does not pay for them
+    fn step_profiled(&mut self) -> Result<StepEvent, AwawaError> {
+        let ip = self.ip;
+        let steps = self.steps;
+        let capacity = self.bubble_abyss.capacity();
+        let (res, double_bubbles) =
+            bubblestack::count_double_bubbles(|| return self.step_untraced());
+        if let Some(profile) = &mut self.profile {
+            if self.steps > steps {
+                let comparison = match &res {
+                    Ok(StepEvent::Executed { comparison, .. }) => *comparison,
+                    _ => None,
+                };
+                profile.record(ip, comparison, self.bubble_abyss.len());
+                let abyss_grew = self.bubble_abyss.capacity() > capacity;
+                profile.record_allocations(double_bubbles, abyss_grew);
             }
-            0x12 => Ok(Awatism::Eql),
-            0x13 => Ok(Awatism::Lss),
-            0x14 => Ok(Awatism::Gr8),
-            0x1F => Ok(Awatism::Trm),
-            a => return Err(AwawaLoadError::UnknownAwatismError(a)),
-        };
+        }
+        return res;
     }
-    fn execute_awatism(&mut self) -> AwawaResult {
-        let awatism = match self.awatism_cache.get(self.ip) {
-            Some(a) => a,
-            None => return Err(AwawaError::EndOfProgramError()),
+
+    fn step_untraced(&mut self) -> Result<StepEvent, AwawaError> {
+        let ip = self.ip;
+        let awatism = match self.program.awatisms().get(ip) {
+            Some(a) => *a,
+            None => return Ok(StepEvent::Halted { ip }),
         };
+        let instr = self.program.code()[ip];
+
+        self.check_fuel()?;
+        self.steps += 1;
 
         if self.verbose >= 1 {
-            print!("[{0}] {awatism} ", self.ip);
+            print!("[{ip}] {0} ", self.program.describe(ip));
         }
 
-        let mut increment_ip = true;
+        let mut output = None;
         let res = match awatism {
-            Awatism::Nop => self.nop(),
-            Awatism::Prn => self.prn(),
-            Awatism::Pr1 => self.pr1(),
-            Awatism::Red => self.red(),
-            Awatism::R3d => self.r3d(),
-            Awatism::Blo(val) => self.blo(*val),
-            Awatism::Sbm(val) => self.sbm(*val),
-            Awatism::Pop => self.pop(),
-            Awatism::Dpl => self.dpl(),
-            Awatism::Srn(val) => self.srn(*val),
-            Awatism::Mrg => self.mrg(),
-            Awatism::Add => self.add(),
-            Awatism::Sub => self.sub(),
-            Awatism::Mul => self.mul(),
-            Awatism::Div => self.div(),
-            Awatism::Cnt => self.cnt(),
-            Awatism::Lbl(val) => self.lbl(*val),
-            Awatism::Jmp(val) => {
-                increment_ip = false;
-                self.jmp(*val)
-            }
-            Awatism::Eql => self.eql(),
-            Awatism::Lss => self.lss(),
-            Awatism::Gr8 => self.gr8(),
-            Awatism::Trm => self.trm(),
+            Awatism::Prn => self.print(print_bubble_awascii).and_then(|s| {
+                output = Some(s);
+                self.flush()?;
+                return Ok(ip + 1);
+            }),
+            Awatism::Pr1 => self.print(print_bubble).and_then(|s| {
+                output = Some(s);
+                self.flush()?;
+                return Ok(ip + 1);
+            }),
+            _ => self.execute(instr),
         };
 
+        let comparison = match (awatism, &res) {
+            (Awatism::Eql | Awatism::Lss | Awatism::Gr8, Ok(next)) => Some(*next == ip + 1),
+            _ => None,
+        };
+
+        if self.verbose >= 1 {
+            match comparison {
+                Some(true) => print!("(true - exec next) "),
+                Some(false) => print!("(false - skip next) "),
+                None => (),
+            }
+        }
         if self.verbose >= 2 {
             println!("-> {0}", self.bubble_abyss);
         } else if self.verbose >= 1 {
-            println!("");
+            println!();
         }
 
-        if increment_ip && res.is_ok() {
-            self.ip += 1;
+        let next = match res {
+            Err(AwawaError::EndOfProgramError()) => return Ok(StepEvent::Halted { ip }),
+            Err(e) => return Err(e),
+            Ok(next) => next,
+        };
+
+        self.check_abyss(instr)?;
+        self.ip = next;
+
+        return Ok(StepEvent::Executed {
+            awatism,
+            ip_before: ip,
+            ip_after: self.ip,
+            comparison,
+            output,
+        });
+    }
+
+    /// Executes `instr` at the instruction pointer.
+    /// Returns the index of the next instruction, the instruction pointer is not changed.
+    #[inline(always)]
+    fn execute(&mut self, instr: Instr) -> Result<usize, AwawaError> {
+        match instr {
+            Instr::Nop => (),
+            Instr::Prn => self.prn()?,
+            Instr::Pr1 => self.pr1()?,
+            Instr::Red => self.red()?,
+            Instr::R3d => self.r3d()?,
+            Instr::Blo(val) => self.blo(val)?,
+            Instr::Sbm(val) => self.sbm(val)?,
+            Instr::Pop => self.pop()?,
+            Instr::Dpl => self.dpl()?,
+            Instr::Srn(val) => self.srn(val)?,
+            Instr::Mrg => self.mrg()?,
+            Instr::Add => self.add()?,
+            Instr::Sub => self.sub()?,
+            Instr::Mul => self.mul()?,
+            Instr::Div => self.div()?,
+            Instr::Cnt => self.cnt()?,
+            Instr::Jmp(target) => return Ok(target),
+            Instr::InvalidJmp(lbl) => return Err(AwawaError::InvalidLabelError(lbl)),
+            Instr::Eql => return self.skip_unless(|ord| ord == Ordering::Equal),
+            Instr::Lss => return self.skip_unless(|ord| ord == Ordering::Less),
+            Instr::Gr8 => return self.skip_unless(|ord| ord == Ordering::Greater),
+            Instr::Trm => return Err(AwawaError::EndOfProgramError()),
         }
+        return Ok(self.ip + 1);
+    }
 
-        return res;
+    /// Returns the index of the next instruction for a comparison, skipping one if it is false
+    fn skip_unless(&mut self, cmp: fn(Ordering) -> bool) -> Result<usize, AwawaError> {
+        if self.bubble_abyss.compare(cmp)? {
+            return Ok(self.ip + 1);
+        }
+        return Ok(self.ip + 2);
+    }
+
+    /// Fails once the instruction budget or the timeout is used up
+    fn check_fuel(&mut self) -> AwawaResult {
+        if let Some(max) = self.limits.max_steps {
+            if self.steps >= max {
+                return Err(AwawaError::FuelExhaustedError(max));
+            }
+        }
+
+        if let Some(timeout) = self.limits.timeout {
+            let started = *self.started.get_or_insert_with(Instant::now);
+            if self.steps.is_multiple_of(TIMEOUT_CHECK_INTERVAL) && started.elapsed() > timeout {
+                return Err(AwawaError::TimeoutError(timeout));
+            }
+        }
+        return Ok(());
+    }
+
+    /// Fails if `instr` grew the Bubble Abyss beyond its limits
+    fn check_abyss(&self, instr: Instr) -> AwawaResult {
+        if let Some(max) = self.limits.max_abyss_size {
+            if self.bubble_abyss.len() > max {
+                return Err(AwawaError::BubbleAbyssTooLargeError(max));
+            }
+        }
+
+        // Only srn and div nest bubbles deeper than their operands
+        if let (Some(max), Instr::Srn(_) | Instr::Div) = (self.limits.max_depth, instr) {
+            if self.bubble_abyss.top_depth_exceeds(max) {
+                return Err(AwawaError::BubbleNestingTooDeepError(max));
+            }
+        }
+        return Ok(());
     }
 
     pub fn nop(&mut self) -> AwawaResult {
@@ -268,27 +516,43 @@ impl<'a> AwaCPU<'a> {
     }
 
     pub fn prn(&mut self) -> AwawaResult {
-        let bubble = self.bubble_abyss.pop()?;
-        return print_bubble_awascii(bubble);
+        return self.print(print_bubble_awascii).map(|_| ());
     }
 
     pub fn pr1(&mut self) -> AwawaResult {
+        return self.print(print_bubble).map(|_| ());
+    }
+
+    /// Pops the top bubble, formats it with `fmt` and writes it to the output.
+    /// Returns the text that was written.
+    fn print(
+        &mut self,
+        fmt: fn(&BubbleItem, &mut String) -> AwawaResult,
+    ) -> Result<String, AwawaError> {
         let bubble = self.bubble_abyss.pop()?;
-        return print_bubble(bubble);
+
+        let mut s = String::new();
+        let res = fmt(&bubble, &mut s);
+        self.output
+            .write_all(s.as_bytes())
+            .map_err(|_| AwawaError::WriteError)?;
+        return res.map(|_| s);
     }
 
     pub fn red(&mut self) -> AwawaResult {
+        // Show prompts before waiting for input
+        self.flush()?;
+
         let mut buf = String::new();
 
-        match std::io::stdin().read_line(&mut buf) {
-            Err(_) => return Err(AwawaError::ReadLineError),
-            _ => (),
+        if self.input.read_line(&mut buf).is_err() {
+            return Err(AwawaError::ReadLineError);
         }
 
-        let filtered: VecDeque<BubbleItem> = buf
+        let filtered: Bubbles = buf
             .chars()
-            .filter_map(|x| awascii::ord(x))
-            .map(|x| BubbleItem::Bubble(x))
+            .filter_map(awascii::ord)
+            .map(BubbleItem::Bubble)
             .collect();
 
         self.bubble_abyss
@@ -298,21 +562,20 @@ impl<'a> AwaCPU<'a> {
     }
 
     pub fn r3d(&mut self) -> AwawaResult {
+        // Show prompts before waiting for input
+        self.flush()?;
+
         let mut buf = String::new();
 
-        match std::io::stdin().read_line(&mut buf) {
-            Err(_) => return Err(AwawaError::ReadLineError),
-            _ => (),
+        if self.input.read_line(&mut buf).is_err() {
+            return Err(AwawaError::ReadLineError);
         }
 
-        let filtered: String = buf.chars().take_while(|x| x.is_digit(10)).collect();
+        let filtered: String = buf.chars().take_while(|x| x.is_ascii_digit()).collect();
 
-        let num = match filtered.parse::<i32>() {
-            Err(_) => return Err(AwawaError::NotANumberError(filtered)),
-            Ok(x) => x,
-        };
+        let num = self.arithmetic.parse(&filtered)?;
 
-        self.bubble_abyss.push_bubble(BubbleItem::Bubble(num));
+        self.bubble_abyss.push_bubble(num);
 
         return Ok(());
     }
@@ -339,58 +602,72 @@ impl<'a> AwaCPU<'a> {
     }
 
     pub fn add(&mut self) -> AwawaResult {
-        let a = self.bubble_abyss.pop()?;
-        let b = self.bubble_abyss.pop()?;
-
-        let res = compute_bubbles(&a, &b, |a, b| a + b);
-
-        self.bubble_abyss.push_bubble(res);
-
-        return Ok(());
+        return self.arithmetic(Op::Add);
     }
 
     pub fn sub(&mut self) -> AwawaResult {
-        let a = self.bubble_abyss.pop()?;
-        let b = self.bubble_abyss.pop()?;
-
-        let res = compute_bubbles(&a, &b, |a, b| a - b);
-
-        self.bubble_abyss.push_bubble(res);
-
-        return Ok(());
+        return self.arithmetic(Op::Sub);
     }
 
     pub fn mul(&mut self) -> AwawaResult {
+        return self.arithmetic(Op::Mul);
+    }
+
+    pub fn div(&mut self) -> AwawaResult {
         let a = self.bubble_abyss.pop()?;
         let b = self.bubble_abyss.pop()?;
 
-        let res = compute_bubbles(&a, &b, |a, b| a * b);
-
-        self.bubble_abyss.push_bubble(res);
+        let arith = self.arithmetic;
+        let res = compute_bubbles(&a, &b, arith, Op::Div).and_then(|res_div| {
+            let res_rem = compute_bubbles(&a, &b, arith, Op::Rem)?;
+            return Ok(BubbleItem::DoubleBubble(vec![res_div, res_rem].into()));
+        });
 
-        return Ok(());
+        return self.push_result(a, b, res);
     }
 
-    pub fn div(&mut self) -> AwawaResult {
+    /// Pops the top two bubbles and pushes the result of applying `op` to them
+    fn arithmetic(&mut self, op: Op) -> AwawaResult {
         let a = self.bubble_abyss.pop()?;
         let b = self.bubble_abyss.pop()?;
 
-        let res_div = compute_bubbles(&a, &b, |a, b| a / b);
-        let res_rem = compute_bubbles(&a, &b, |a, b| a % b);
+        let res = compute_bubbles(&a, &b, self.arithmetic, op);
 
-        let res = BubbleItem::DoubleBubble(vec![res_div, res_rem].into());
-        self.bubble_abyss.push_bubble(res);
+        return self.push_result(a, b, res);
+    }
 
-        return Ok(());
+    /// Pushes `res`, or puts the operands `a` and `b` back if it failed so the
+    /// Bubble Abyss shows what the failing awatism was working on.
+    fn push_result(
+        &mut self,
+        a: BubbleItem,
+        b: BubbleItem,
+        res: Result<BubbleItem, AwawaError>,
+    ) -> AwawaResult {
+        match res {
+            Ok(res) => {
+                self.bubble_abyss.push_bubble(res);
+                return Ok(());
+            }
+            Err(e) => {
+                self.bubble_abyss.push_bubble(b);
+                self.bubble_abyss.push_bubble(a);
+                return Err(e);
+            }
+        }
     }
 
     pub fn mrg(&mut self) -> AwawaResult {
         let a = self.bubble_abyss.pop()?;
         let b = self.bubble_abyss.pop()?;
 
-        let res = merge(a, b);
+        // Merging two bubbles adds them, which can overflow
+        if !is_double(&a) && !is_double(&b) {
+            let res = self.arithmetic.apply(Op::Add, &a, &b);
+            return self.push_result(a, b, res);
+        }
 
-        self.bubble_abyss.push_bubble(res);
+        self.bubble_abyss.push_bubble(merge(a, b));
 
         return Ok(());
     }
@@ -404,37 +681,29 @@ impl<'a> AwaCPU<'a> {
     }
 
     pub fn jmp(&mut self, val: u8) -> AwawaResult {
-        match self.labels.get(&val) {
+        match self.program.get_label(val) {
             None => return Err(AwawaError::InvalidLabelError(val)),
             Some(target) => {
-                self.ip = *target;
+                self.ip = target;
                 return Ok(());
             }
         }
     }
 
     pub fn eql(&mut self) -> AwawaResult {
-        return self.compare_and_jmp(|x, y| x == y);
+        return self.compare_and_jmp(|ord| ord == Ordering::Equal);
     }
 
     pub fn lss(&mut self) -> AwawaResult {
-        return self.compare_and_jmp(|x, y| x < y);
+        return self.compare_and_jmp(|ord| ord == Ordering::Less);
     }
 
     pub fn gr8(&mut self) -> AwawaResult {
-        return self.compare_and_jmp(|x, y| x > y);
+        return self.compare_and_jmp(|ord| ord == Ordering::Greater);
     }
 
-    fn compare_and_jmp(&mut self, cmp: fn(i32, i32) -> bool) -> AwawaResult {
-        let res = self.bubble_abyss.compare(cmp)?;
-        if self.verbose > 0 {
-            if res {
-                print!("(true - exec next) ");
-            } else {
-                print!("(false - skip next) ");
-            }
-        }
-        if !res {
+    fn compare_and_jmp(&mut self, cmp: fn(Ordering) -> bool) -> AwawaResult {
+        if !self.bubble_abyss.compare(cmp)? {
             self.ip += 1;
         }
         return Ok(());
@@ -445,136 +714,145 @@ impl<'a> AwaCPU<'a> {
     }
 }
 
+fn is_double(bubble: &BubbleItem) -> bool {
+    return matches!(bubble, BubbleItem::DoubleBubble(_));
+}
+
 fn merge(a: BubbleItem, b: BubbleItem) -> BubbleItem {
     let res = match (a, b) {
-        (BubbleItem::Bubble(va), BubbleItem::Bubble(vb)) => BubbleItem::Bubble(va + vb),
+        (BubbleItem::DoubleBubble(mut va), BubbleItem::DoubleBubble(vb)) => {
+            va.append(vb);
+            BubbleItem::DoubleBubble(va)
+        }
 
-        (BubbleItem::DoubleBubble(mut va), BubbleItem::Bubble(vb)) => {
-            va.push_back(BubbleItem::Bubble(vb));
+        (BubbleItem::DoubleBubble(mut va), b) => {
+            va.push_back(b);
             BubbleItem::DoubleBubble(va)
         }
 
-        (BubbleItem::Bubble(va), BubbleItem::DoubleBubble(mut vb)) => {
-            vb.push_front(BubbleItem::Bubble(va));
+        (a, BubbleItem::DoubleBubble(mut vb)) => {
+            vb.push_front(a);
             BubbleItem::DoubleBubble(vb)
         }
 
-        (BubbleItem::DoubleBubble(mut va), BubbleItem::DoubleBubble(mut vb)) => {
-            va.append(&mut vb);
-            BubbleItem::DoubleBubble(va)
-        }
+        (_, _) => unreachable!("mrg adds two bubbles"),
     };
     return res;
 }
 
-fn compute_bubbles(a: &BubbleItem, b: &BubbleItem, compute: fn(&i32, &i32) -> i32) -> BubbleItem {
-    let res = match (a, b) {
-        (BubbleItem::Bubble(va), BubbleItem::Bubble(vb)) => BubbleItem::Bubble(compute(va, vb)),
-
-        (BubbleItem::DoubleBubble(va), BubbleItem::Bubble(_vb)) => {
-            BubbleItem::DoubleBubble(va.iter().map(|x| compute_bubbles(x, b, compute)).collect())
-        }
-        (BubbleItem::Bubble(_va), BubbleItem::DoubleBubble(vb)) => {
-            BubbleItem::DoubleBubble(vb.iter().map(|x| compute_bubbles(a, x, compute)).collect())
-        }
-        (BubbleItem::DoubleBubble(va), BubbleItem::DoubleBubble(vb)) => {
-            let ia = va.iter();
-            let ib = vb.iter();
-            BubbleItem::DoubleBubble(
-                std::iter::zip(ia, ib)
-                    .map(|(bubble_a, bubble_b)| compute_bubbles(bubble_a, bubble_b, compute))
-                    .collect(),
-            )
-        }
-    };
-
-    return res;
+/// The pairs of bubbles an arithmetic operation on a Double Bubble is applied to
+enum Pairs<'a> {
+    /// Two Double Bubbles, paired up until the shorter one runs out
+    Zip(Iter<'a, BubbleItem>, Iter<'a, BubbleItem>),
+    /// A Double Bubble and a bubble applied to each of its bubbles
+    Left(Iter<'a, BubbleItem>, &'a BubbleItem),
+    /// A bubble applied to each bubble of a Double Bubble
+    Right(&'a BubbleItem, Iter<'a, BubbleItem>),
 }
 
-fn print_bubble(bubble: BubbleItem) -> AwawaResult {
-    match bubble {
-        bubblestack::BubbleItem::Bubble(val) => {
-            print!("{val} ");
-            return Ok(());
-        }
-        BubbleItem::DoubleBubble(v) => {
-            for val in v {
-                print_bubble(val)?;
+impl<'a> Pairs<'a> {
+    /// Returns the pairs for `a` and `b`, or `None` if neither is a Double Bubble
+    fn new(a: &'a BubbleItem, b: &'a BubbleItem) -> Option<Self> {
+        return match (a, b) {
+            (BubbleItem::DoubleBubble(va), BubbleItem::DoubleBubble(vb)) => {
+                Some(Self::Zip(va.iter(), vb.iter()))
             }
-            return Ok(());
-        }
+            (BubbleItem::DoubleBubble(va), _) => Some(Self::Left(va.iter(), b)),
+            (_, BubbleItem::DoubleBubble(vb)) => Some(Self::Right(a, vb.iter())),
+            (_, _) => None,
+        };
     }
 }
 
-fn print_bubble_awascii(bubble: BubbleItem) -> Result<(), AwawaError> {
-    match bubble {
-        bubblestack::BubbleItem::Bubble(val) => {
-            let x = awascii::awascii(val.into())?;
-            print!("{x}");
-            return Ok(());
-        }
-        BubbleItem::DoubleBubble(v) => {
-            for val in v {
-                print_bubble_awascii(val)?;
-            }
-            return Ok(());
-        }
+impl<'a> Iterator for Pairs<'a> {
+    type Item = (&'a BubbleItem, &'a BubbleItem);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        return match self {
+            Self::Zip(ia, ib) => Some((ia.next()?, ib.next()?)),
+            Self::Left(ia, b) => Some((ia.next()?, *b)),
+            Self::Right(a, ib) => Some((*a, ib.next()?)),
+        };
     }
 }
 
-fn read_n_arg(awa_it: &mut Chars<'_>, n: usize) -> Result<u8, AwawaLoadError> {
-    let x = read_n(awa_it, n)?;
+/// Applies `op` to `a` and `b`, element-wise for Double Bubbles.
+/// Nested Double Bubbles are handled with a heap allocated stack instead of recursion.
+fn compute_bubbles(
+    a: &BubbleItem,
+    b: &BubbleItem,
+    arith: Arithmetic,
+    op: Op,
+) -> Result<BubbleItem, AwawaError> {
+    let mut stack = match Pairs::new(a, b) {
+        None => return arith.apply(op, a, b),
+        Some(pairs) => vec![(pairs, VecDeque::new())],
+    };
 
-    match x {
-        None => return Err(AwawaLoadError::MalformedAwatismError),
-        Some(val) => return Ok(val),
+    loop {
+        let (pairs, res) = stack.last_mut().expect("stack is not empty");
+        match pairs.next() {
+            Some((x, y)) => match Pairs::new(x, y) {
+                Some(pairs) => stack.push((pairs, VecDeque::new())),
+                None => res.push_back(arith.apply(op, x, y)?),
+            },
+            None => {
+                let (_, res) = stack.pop().expect("stack is not empty");
+                let res = BubbleItem::DoubleBubble(res.into());
+                match stack.last_mut() {
+                    Some((_, parent)) => parent.push_back(res),
+                    None => return Ok(res),
+                }
+            }
+        }
     }
 }
 
-fn read_n(awa_it: &mut Chars<'_>, n: usize) -> Result<Option<u8>, AwawaLoadError> {
-    assert!(n <= 8);
-
-    let mut r = 0;
-    let mut res: u8 = 0;
-
-    while r < n {
-        let mut c = match awa_it.next() {
-            None => return Ok(None),
-            Some(x) => x,
-        };
-        match c {
-            'A' | 'a' => {
-                c = awa_it.next().ok_or(AwawaLoadError::AwawaParseError)?;
-                if c != 'w' && c != 'W' {
-                    return Err(AwawaLoadError::AwawaParseError);
-                }
-                c = awa_it.next().ok_or(AwawaLoadError::AwawaParseError)?;
-                if c != 'a' && c != 'A' {
-                    return Err(AwawaLoadError::AwawaParseError);
-                }
-                r += 1;
-                res = res << 1;
+fn print_bubble(bubble: &BubbleItem, out: &mut String) -> AwawaResult {
+    for step in bubble.walk() {
+        match step {
+            Walk::Bubble(BubbleItem::Bubble(val)) => {
+                let _ = write!(out, "{val} ");
             }
-            'W' | 'w' => {
-                c = awa_it.next().ok_or(AwawaLoadError::AwawaParseError)?;
-                if c != 'a' && c != 'A' {
-                    return Err(AwawaLoadError::AwawaParseError);
-                }
-                r += 1;
-                res = (res << 1) | 1;
+            #[cfg(feature = "bignum")]
+            Walk::Bubble(BubbleItem::BigBubble(val)) => {
+                let _ = write!(out, "{val} ");
             }
             _ => (),
         }
     }
+    return Ok(());
+}
 
-    return Ok(Some(res));
+fn print_bubble_awascii(bubble: &BubbleItem, out: &mut String) -> AwawaResult {
+    for step in bubble.walk() {
+        match step {
+            Walk::Bubble(BubbleItem::Bubble(val)) => {
+                out.push(awascii::awascii(*val)?);
+            }
+            #[cfg(feature = "bignum")]
+            Walk::Bubble(BubbleItem::BigBubble(val)) => {
+                return Err(AwawaError::NumberTooLargeError(val.to_string()));
+            }
+            _ => (),
+        }
+    }
+    return Ok(());
 }
 
 #[cfg(test)]
+#[allow(clippy::identity_op)]
 mod tests {
-    use crate::{awacpu::bubblestack::BubbleItem, errors::AwawaResult};
+    use crate::{
+        awacpu::{arithmetic::Arithmetic, bubblestack::BubbleItem},
+        errors::{AwawaError, AwawaResult},
+    };
 
     use super::AwaCPU;
+    use crate::program::Program;
+    use std::sync::LazyLock;
+
+    static EMPTY: LazyLock<Program> = LazyLock::new(Program::default);
 
     fn assert_bubble_abyss(mut cpu: AwaCPU, v: Vec<BubbleItem>) {
         // assert_eq!(cpu.bubble_abyss.len(), v.len());
@@ -593,7 +871,7 @@ mod tests {
 
     #[test]
     fn nop() -> AwawaResult {
-        let mut cpu = AwaCPU::new("".chars(), 0);
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
         cpu.nop()?;
         assert_eq!(cpu.bubble_abyss.len(), 0);
 
@@ -602,7 +880,7 @@ mod tests {
 
     #[test]
     fn blo() -> AwawaResult {
-        let mut cpu = AwaCPU::new("".chars(), 0);
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
         cpu.blo(1)?;
         cpu.blo(2)?;
         cpu.blo(3)?;
@@ -612,7 +890,7 @@ mod tests {
 
     #[test]
     fn sbm() -> AwawaResult {
-        let mut cpu = AwaCPU::new("".chars(), 0);
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
         cpu.blo(1)?;
         cpu.blo(2)?;
         cpu.blo(3)?;
@@ -626,7 +904,7 @@ mod tests {
 
     #[test]
     fn srn() -> AwawaResult {
-        let mut cpu = AwaCPU::new("".chars(), 0);
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
 
         cpu.blo(1)?;
         cpu.blo(2)?;
@@ -653,7 +931,7 @@ mod tests {
 
     #[test]
     fn pop() -> AwawaResult {
-        let mut cpu = AwaCPU::new("".chars(), 0);
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
 
         cpu.blo(1)?;
         cpu.blo(2)?;
@@ -669,7 +947,7 @@ mod tests {
 
     #[test]
     fn dpl() -> AwawaResult {
-        let mut cpu = AwaCPU::new("".chars(), 0);
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
 
         cpu.blo(1)?;
         cpu.blo(2)?;
@@ -697,7 +975,7 @@ mod tests {
         ];
         assert_bubble_abyss(cpu, v);
 
-        let mut cpu = AwaCPU::new("".chars(), 0);
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
 
         cpu.blo(1)?;
         cpu.blo(2)?;
@@ -707,10 +985,41 @@ mod tests {
         return Ok(());
     }
 
+    #[test]
+    fn dpl_copy_on_write() -> AwawaResult {
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
+
+        cpu.blo(1)?;
+        cpu.blo(2)?;
+        cpu.srn(2)?;
+        cpu.dpl()?;
+
+        // Changing one copy leaves the other alone
+        cpu.blo(3)?;
+        cpu.mrg()?;
+        cpu.sbm(1)?;
+        cpu.pop()?;
+
+        let v = vec![
+            BubbleItem::Bubble(2),
+            BubbleItem::Bubble(1),
+            BubbleItem::DoubleBubble(
+                vec![
+                    BubbleItem::Bubble(3),
+                    BubbleItem::Bubble(2),
+                    BubbleItem::Bubble(1),
+                ]
+                .into(),
+            ),
+        ];
+        assert_bubble_abyss(cpu, v);
+        return Ok(());
+    }
+
     #[test]
     fn add() -> AwawaResult {
         // Bubble + Bubble
-        let mut cpu = AwaCPU::new("".chars(), 0);
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
 
         cpu.blo(10)?;
         cpu.blo(1)?;
@@ -718,7 +1027,7 @@ mod tests {
         assert_bubble_abyss_single(cpu, vec![1 + 10]);
 
         // Bubble + DoubleBubble
-        let mut cpu = AwaCPU::new("".chars(), 0);
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
 
         cpu.blo(1)?;
         cpu.blo(2)?;
@@ -737,7 +1046,7 @@ mod tests {
         assert_bubble_abyss(cpu, v);
 
         // DoubleBubble + Bubble
-        let mut cpu = AwaCPU::new("".chars(), 0);
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
 
         cpu.blo(10)?;
         cpu.blo(1)?;
@@ -756,7 +1065,7 @@ mod tests {
         assert_bubble_abyss(cpu, v);
 
         // DoubleBubble + DoubleBubble
-        let mut cpu = AwaCPU::new("".chars(), 0);
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
 
         // ( (10, 11), (12, 13) ) + (1, 2, 3)
         cpu.blo(10)?;
@@ -792,7 +1101,7 @@ mod tests {
     #[test]
     fn sub() -> AwawaResult {
         // Bubble - Bubble
-        let mut cpu = AwaCPU::new("".chars(), 0);
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
 
         cpu.blo(10)?;
         cpu.blo(1)?;
@@ -800,7 +1109,7 @@ mod tests {
         assert_bubble_abyss_single(cpu, vec![1 - 10]);
 
         // Bubble - DoubleBubble
-        let mut cpu = AwaCPU::new("".chars(), 0);
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
 
         cpu.blo(1)?;
         cpu.blo(2)?;
@@ -819,7 +1128,7 @@ mod tests {
         assert_bubble_abyss(cpu, v);
 
         // DoubleBubble - Bubble
-        let mut cpu = AwaCPU::new("".chars(), 0);
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
 
         cpu.blo(10)?;
         cpu.blo(1)?;
@@ -838,7 +1147,7 @@ mod tests {
         assert_bubble_abyss(cpu, v);
 
         // DoubleBubble - DoubleBubble
-        let mut cpu = AwaCPU::new("".chars(), 0);
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
 
         // ( (10, 11), (12, 13) ) - (1, 2, 3)
         cpu.blo(10)?;
@@ -874,7 +1183,7 @@ mod tests {
     #[test]
     fn mul() -> AwawaResult {
         // Bubble * Bubble
-        let mut cpu = AwaCPU::new("".chars(), 0);
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
 
         cpu.blo(10)?;
         cpu.blo(2)?;
@@ -882,7 +1191,7 @@ mod tests {
         assert_bubble_abyss_single(cpu, vec![2 * 10]);
 
         // Bubble * DoubleBubble
-        let mut cpu = AwaCPU::new("".chars(), 0);
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
 
         cpu.blo(1)?;
         cpu.blo(2)?;
@@ -901,7 +1210,7 @@ mod tests {
         assert_bubble_abyss(cpu, v);
 
         // DoubleBubble * Bubble
-        let mut cpu = AwaCPU::new("".chars(), 0);
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
 
         cpu.blo(10)?;
         cpu.blo(1)?;
@@ -920,7 +1229,7 @@ mod tests {
         assert_bubble_abyss(cpu, v);
 
         // DoubleBubble * DoubleBubble
-        let mut cpu = AwaCPU::new("".chars(), 0);
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
 
         // ( (10, 11), (12, 13) ) - (1, 2, 3)
         cpu.blo(10)?;
@@ -956,7 +1265,7 @@ mod tests {
     #[test]
     fn mrg() -> AwawaResult {
         // Bubble mrg Bubble
-        let mut cpu = AwaCPU::new("".chars(), 0);
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
 
         cpu.blo(10)?;
         cpu.blo(2)?;
@@ -964,7 +1273,7 @@ mod tests {
         assert_bubble_abyss_single(cpu, vec![2 + 10]);
 
         // Bubble mrg DoubleBubble
-        let mut cpu = AwaCPU::new("".chars(), 0);
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
 
         cpu.blo(1)?;
         cpu.blo(2)?;
@@ -984,7 +1293,7 @@ mod tests {
         assert_bubble_abyss(cpu, v);
 
         // DoubleBubble mrg Bubble
-        let mut cpu = AwaCPU::new("".chars(), 0);
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
 
         cpu.blo(10)?;
         cpu.blo(1)?;
@@ -1004,7 +1313,7 @@ mod tests {
         assert_bubble_abyss(cpu, v);
 
         // DoubleBubble mrg DoubleBubble
-        let mut cpu = AwaCPU::new("".chars(), 0);
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
 
         // ( (10, 11), (12, 13) ) - (1, 2, 3)
         cpu.blo(10)?;
@@ -1041,7 +1350,7 @@ mod tests {
     }
     #[test]
     fn div() -> AwawaResult {
-        let mut cpu = AwaCPU::new("".chars(), 0);
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
 
         // ( (10, 11), (12, 13) ) - (1, 2, 3)
         cpu.blo(2)?;
@@ -1063,4 +1372,194 @@ mod tests {
         assert_bubble_abyss(cpu, v);
         return Ok(());
     }
+
+    #[test]
+    fn div_by_zero() -> AwawaResult {
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
+
+        cpu.blo(0)?;
+        cpu.blo(7)?;
+        assert!(matches!(cpu.div(), Err(AwawaError::DivisionByZeroError)));
+
+        // The operands stay on the Bubble Abyss
+        assert_bubble_abyss_single(cpu, vec![7, 0]);
+        return Ok(());
+    }
+
+    #[test]
+    fn overflow() -> AwawaResult {
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
+        cpu.bubble_abyss.push_bubble(BubbleItem::Bubble(1));
+        cpu.bubble_abyss.push_bubble(BubbleItem::Bubble(i32::MAX));
+        assert!(matches!(cpu.add(), Err(AwawaError::OverflowError)));
+        assert!(matches!(cpu.mrg(), Err(AwawaError::OverflowError)));
+
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
+        cpu.blo(-1)?;
+        cpu.bubble_abyss.push_bubble(BubbleItem::Bubble(i32::MIN));
+        assert!(matches!(cpu.div(), Err(AwawaError::OverflowError)));
+        assert!(matches!(cpu.mul(), Err(AwawaError::OverflowError)));
+        assert_bubble_abyss_single(cpu, vec![i32::MIN, -1]);
+        return Ok(());
+    }
+
+    #[test]
+    fn arithmetic_modes() -> AwawaResult {
+        fn compute(
+            arith: Arithmetic,
+            a: i32,
+            b: i32,
+            op: fn(&mut AwaCPU<'static>) -> AwawaResult,
+        ) -> Result<BubbleItem, AwawaError> {
+            let mut cpu = AwaCPU::new(&EMPTY, 0);
+            cpu.set_arithmetic(arith);
+            cpu.bubble_abyss.push_bubble(BubbleItem::Bubble(b));
+            cpu.bubble_abyss.push_bubble(BubbleItem::Bubble(a));
+            return op(&mut cpu).map(|_| cpu.bubble_abyss.pop().unwrap());
+        }
+
+        let res = compute(Arithmetic::Wrapping, i32::MAX, 1, AwaCPU::add)?;
+        assert_eq!(res, BubbleItem::Bubble(i32::MIN));
+        let res = compute(Arithmetic::Wrapping, i32::MIN, 2, AwaCPU::mrg)?;
+        assert_eq!(res, BubbleItem::Bubble(i32::MIN + 2));
+        let res = compute(Arithmetic::Saturating, i32::MIN, 2, AwaCPU::mul)?;
+        assert_eq!(res, BubbleItem::Bubble(i32::MIN));
+        let res = compute(Arithmetic::Saturating, i32::MIN, -1, AwaCPU::div)?;
+        let v = vec![BubbleItem::Bubble(i32::MAX), BubbleItem::Bubble(0)];
+        assert_eq!(res, BubbleItem::DoubleBubble(v.into()));
+
+        let res = compute(Arithmetic::Wrapping, 1, 0, AwaCPU::div);
+        assert!(matches!(res, Err(AwawaError::DivisionByZeroError)));
+        return Ok(());
+    }
+
+    #[cfg(feature = "bignum")]
+    #[test]
+    fn bignum() -> AwawaResult {
+        let mut out = vec![];
+        {
+            let mut cpu = AwaCPU::with_io(&EMPTY, 0, "".as_bytes(), &mut out);
+            cpu.set_arithmetic(Arithmetic::Bignum);
+
+            // 65536 * 65536 * 65536 / 65536 / 65536
+            cpu.bubble_abyss.push_bubble(BubbleItem::Bubble(65536));
+            cpu.dpl()?;
+            cpu.dpl()?;
+            cpu.mul()?;
+            cpu.mul()?;
+            cpu.dpl()?;
+            cpu.pr1()?;
+            cpu.bubble_abyss.push_bubble(BubbleItem::Bubble(65536));
+            cpu.sbm(1)?;
+            cpu.div()?;
+            cpu.pr1()?;
+        }
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "281474976710656 4294967296 0 "
+        );
+        return Ok(());
+    }
+
+    /// Surrounds `val` with `depth` Double Bubbles
+    fn nest(cpu: &mut AwaCPU, val: i8, depth: usize) -> AwawaResult {
+        cpu.blo(val)?;
+        for _ in 0..depth {
+            cpu.srn(1)?;
+        }
+        return Ok(());
+    }
+
+    #[test]
+    fn nesting_depth() -> AwawaResult {
+        let mut cpu = AwaCPU::new(&EMPTY, 0);
+        let depth = |cpu: &AwaCPU| cpu.bubble_abyss.iter().next().unwrap().depth();
+
+        cpu.blo(1)?;
+        assert_eq!(depth(&cpu), 0);
+        cpu.srn(1)?;
+        cpu.srn(1)?;
+        assert_eq!(depth(&cpu), 2);
+        cpu.blo(2)?;
+        cpu.srn(1)?;
+        cpu.mrg()?;
+        assert_eq!(depth(&cpu), 2);
+        cpu.blo(3)?;
+        cpu.div()?;
+        assert_eq!(depth(&cpu), 3);
+        return Ok(());
+    }
+
+    #[test]
+    fn deep_nesting() -> AwawaResult {
+        const DEPTH: usize = 1_000_000;
+
+        let mut out = vec![];
+        {
+            let mut cpu = AwaCPU::with_io(&EMPTY, 0, "".as_bytes(), &mut out);
+            nest(&mut cpu, 1, DEPTH)?;
+            assert!(cpu.bubble_abyss.top_depth_exceeds(DEPTH - 1));
+            assert!(!cpu.bubble_abyss.top_depth_exceeds(DEPTH));
+
+            cpu.dpl()?;
+            cpu.blo(2)?;
+            cpu.add()?;
+
+            let mut expected = AwaCPU::new(&EMPTY, 0);
+            nest(&mut expected, 3, DEPTH)?;
+            let sum = cpu.bubble_abyss.pop()?;
+            assert_eq!(sum.depth(), DEPTH);
+            assert_eq!(sum, expected.bubble_abyss.pop()?);
+            assert_ne!(sum, cpu.bubble_abyss.pop()?);
+
+            let text = "(".repeat(DEPTH) + "'w'" + &")".repeat(DEPTH);
+            assert_eq!(sum.to_string(), text);
+            let debug = "DoubleBubble([".repeat(DEPTH) + "Bubble(3)" + &"])".repeat(DEPTH);
+            assert_eq!(format!("{sum:?}"), debug);
+
+            cpu.bubble_abyss.push_bubble(sum);
+            cpu.pr1()?;
+            nest(&mut cpu, 7, DEPTH)?;
+            cpu.prn()?;
+        }
+        assert_eq!(String::from_utf8(out).unwrap(), "3 Y");
+        return Ok(());
+    }
+
+    #[test]
+    fn prn() -> AwawaResult {
+        let mut out = vec![];
+        {
+            let mut cpu = AwaCPU::with_io(&EMPTY, 0, "".as_bytes(), &mut out);
+            cpu.blo(10)?;
+            cpu.blo(9)?;
+            cpu.srn(2)?;
+            cpu.prn()?;
+            cpu.blo(-3)?;
+            cpu.pr1()?;
+        }
+        assert_eq!(String::from_utf8(out).unwrap(), "OS-3 ");
+        return Ok(());
+    }
+
+    #[test]
+    fn red() -> AwawaResult {
+        let mut cpu = AwaCPU::with_io(&EMPTY, 0, "Awa?\n42 awa\n".as_bytes(), vec![]);
+        cpu.red()?;
+        cpu.r3d()?;
+        let v = vec![
+            BubbleItem::Bubble(42),
+            BubbleItem::DoubleBubble(
+                vec![
+                    BubbleItem::Bubble(0),
+                    BubbleItem::Bubble(3),
+                    BubbleItem::Bubble(2),
+                    BubbleItem::Bubble(63),
+                ]
+                .into(),
+            ),
+        ];
+        assert_bubble_abyss(cpu, v);
+        return Ok(());
+    }
 }
@@ -0,0 +1,168 @@
+use std::{fmt::Display, str::FromStr};
+
+#[cfg(feature = "bignum")]
+use num_bigint::BigInt;
+
+use crate::{awacpu::bubblestack::BubbleItem, errors::AwawaError};
+
+/// How `4dd`, `sub`, `mul`, `div` and `mrg` treat results that do not fit a bubble
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Arithmetic {
+    /// Overflow is an `AwawaError::OverflowError`
+    #[default]
+    Checked,
+    /// Results wrap around at the boundary of `i32`
+    Wrapping,
+    /// Results are clamped to `i32::MIN` and `i32::MAX`
+    Saturating,
+    /// Results that do not fit an `i32` become arbitrary precision bubbles
+    #[cfg(feature = "bignum")]
+    Bignum,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl Arithmetic {
+    /// Applies `op` to two bubbles that are not Double Bubbles
+    pub fn apply(self, op: Op, a: &BubbleItem, b: &BubbleItem) -> Result<BubbleItem, AwawaError> {
+        return match (a, b) {
+            (BubbleItem::Bubble(va), BubbleItem::Bubble(vb)) => self.apply_i32(op, *va, *vb),
+            #[cfg(feature = "bignum")]
+            (BubbleItem::BigBubble(_), _) | (_, BubbleItem::BigBubble(_)) => {
+                apply_big(op, to_big(a), to_big(b))
+            }
+            _ => unreachable!("Double Bubbles are handled by compute_bubbles"),
+        };
+    }
+
+    fn apply_i32(self, op: Op, a: i32, b: i32) -> Result<BubbleItem, AwawaError> {
+        if b == 0 && (op == Op::Div || op == Op::Rem) {
+            return Err(AwawaError::DivisionByZeroError);
+        }
+
+        let res = match self {
+            Self::Checked => checked(op, a, b).ok_or(AwawaError::OverflowError)?,
+            Self::Wrapping => match op {
+                Op::Add => a.wrapping_add(b),
+                Op::Sub => a.wrapping_sub(b),
+                Op::Mul => a.wrapping_mul(b),
+                Op::Div => a.wrapping_div(b),
+                Op::Rem => a.wrapping_rem(b),
+            },
+            Self::Saturating => match op {
+                Op::Add => a.saturating_add(b),
+                Op::Sub => a.saturating_sub(b),
+                Op::Mul => a.saturating_mul(b),
+                Op::Div => a.saturating_div(b),
+                // i32::MIN % -1 is the only overflowing remainder and its result is 0
+                Op::Rem => a.wrapping_rem(b),
+            },
+            #[cfg(feature = "bignum")]
+            Self::Bignum => match checked(op, a, b) {
+                Some(res) => res,
+                None => return apply_big(op, BigInt::from(a), BigInt::from(b)),
+            },
+        };
+        return Ok(BubbleItem::Bubble(res));
+    }
+
+    /// Parses the number typed for `r3d`
+    pub fn parse(self, s: &str) -> Result<BubbleItem, AwawaError> {
+        #[cfg(feature = "bignum")]
+        if self == Self::Bignum {
+            return match s.parse::<BigInt>() {
+                Err(_) => Err(AwawaError::NotANumberError(s.to_string())),
+                Ok(x) => Ok(normalize(x)),
+            };
+        }
+
+        return match s.parse::<i32>() {
+            Err(_) => Err(AwawaError::NotANumberError(s.to_string())),
+            Ok(x) => Ok(BubbleItem::Bubble(x)),
+        };
+    }
+}
+
+fn checked(op: Op, a: i32, b: i32) -> Option<i32> {
+    return match op {
+        Op::Add => a.checked_add(b),
+        Op::Sub => a.checked_sub(b),
+        Op::Mul => a.checked_mul(b),
+        Op::Div => a.checked_div(b),
+        Op::Rem => a.checked_rem(b),
+    };
+}
+
+#[cfg(feature = "bignum")]
+fn to_big(bubble: &BubbleItem) -> BigInt {
+    return match bubble {
+        BubbleItem::Bubble(v) => BigInt::from(*v),
+        BubbleItem::BigBubble(v) => v.clone(),
+        BubbleItem::DoubleBubble(_) => {
+            unreachable!("Double Bubbles are handled by compute_bubbles")
+        }
+    };
+}
+
+#[cfg(feature = "bignum")]
+fn apply_big(op: Op, a: BigInt, b: BigInt) -> Result<BubbleItem, AwawaError> {
+    if b == BigInt::default() && (op == Op::Div || op == Op::Rem) {
+        return Err(AwawaError::DivisionByZeroError);
+    }
+
+    let res = match op {
+        Op::Add => a + b,
+        Op::Sub => a - b,
+        Op::Mul => a * b,
+        Op::Div => a / b,
+        Op::Rem => a % b,
+    };
+    return Ok(normalize(res));
+}
+
+/// Keeps numbers that fit an `i32` in a plain bubble
+#[cfg(feature = "bignum")]
+fn normalize(val: BigInt) -> BubbleItem {
+    return match i32::try_from(&val) {
+        Ok(v) => BubbleItem::Bubble(v),
+        Err(_) => BubbleItem::BigBubble(val),
+    };
+}
+
+impl Display for Arithmetic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            Self::Checked => write!(f, "checked"),
+            Self::Wrapping => write!(f, "wrapping"),
+            Self::Saturating => write!(f, "saturating"),
+            #[cfg(feature = "bignum")]
+            Self::Bignum => write!(f, "bignum"),
+        };
+    }
+}
+
+impl FromStr for Arithmetic {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "checked" => Ok(Self::Checked),
+            "wrapping" => Ok(Self::Wrapping),
+            "saturating" => Ok(Self::Saturating),
+            #[cfg(feature = "bignum")]
+            "bignum" => Ok(Self::Bignum),
+            #[cfg(not(feature = "bignum"))]
+            "bignum" => Err(String::from(
+                "bignum arithmetic requires the 'bignum' feature",
+            )),
+            _ => Err(format!("unknown arithmetic '{s}'")),
+        };
+    }
+}
@@ -0,0 +1,204 @@
+use std::{iter::Peekable, str::Chars};
+
+use crate::{
+    awacpu::Awatism,
+    errors::{AwatalkSpan, AwawaLoadError, AwawaLoadResult},
+};
+
+/// Position of a character in Awatalk
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+    /// 0-based character offset
+    pub offset: usize,
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based character column
+    pub column: usize,
+}
+
+/// Reads awa and wa tokens from Awatalk while keeping track of the position in the source
+pub struct AwatalkReader<'a> {
+    chars: Peekable<Chars<'a>>,
+    pos: Position,
+}
+
+impl<'a> AwatalkReader<'a> {
+    pub fn new(chars: Chars<'a>) -> AwatalkReader<'a> {
+        return Self {
+            chars: chars.peekable(),
+            pos: Position {
+                offset: 0,
+                line: 1,
+                column: 1,
+            },
+        };
+    }
+
+    pub fn position(&self) -> Position {
+        return self.pos;
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        self.pos.offset += 1;
+        if c == '\n' {
+            self.pos.line += 1;
+            self.pos.column = 1;
+        } else {
+            self.pos.column += 1;
+        }
+        return Some(c);
+    }
+
+    /// Skips everything that cannot start an awa or wa. Returns false at the end of the Awatalk.
+    pub fn skip_ignored(&mut self) -> bool {
+        while let Some(c) = self.chars.peek() {
+            if matches!(c, 'A' | 'a' | 'W' | 'w') {
+                return true;
+            }
+            self.next();
+        }
+        return false;
+    }
+
+    /// Creates a span covering the Awatalk from `start` to the current position
+    pub fn span(&self, start: Position) -> AwatalkSpan {
+        return AwatalkSpan {
+            index: 0,
+            offset: start.offset,
+            line: start.line,
+            column: start.column,
+            len: self.pos.offset - start.offset,
+        };
+    }
+
+    /// Reads `n` bits, awa is 0 and wa is 1.
+    /// Returns `None` if the Awatalk ends before all bits were read.
+    pub fn read_n(&mut self, n: usize) -> Result<Option<u8>, AwawaLoadError> {
+        assert!(n <= 8);
+
+        let mut r = 0;
+        let mut res: u8 = 0;
+
+        while r < n {
+            if !self.skip_ignored() {
+                return Ok(None);
+            }
+
+            let start = self.pos;
+            match self.next() {
+                Some('A' | 'a') => {
+                    if !matches!(self.next(), Some('W' | 'w')) {
+                        return Err(AwawaLoadError::AwawaParseError(self.span(start)));
+                    }
+                    if !matches!(self.next(), Some('A' | 'a')) {
+                        return Err(AwawaLoadError::AwawaParseError(self.span(start)));
+                    }
+                    r += 1;
+                    res <<= 1;
+                }
+                _ => {
+                    if !matches!(self.next(), Some('A' | 'a')) {
+                        return Err(AwawaLoadError::AwawaParseError(self.span(start)));
+                    }
+                    r += 1;
+                    res = (res << 1) | 1;
+                }
+            }
+        }
+
+        return Ok(Some(res));
+    }
+
+    /// Reads an `n` bit argument of the awatism starting at `start`
+    pub fn read_arg(&mut self, n: usize, start: Position) -> Result<u8, AwawaLoadError> {
+        match self.read_n(n) {
+            Ok(Some(val)) => return Ok(val),
+            Err(e) if !matches!(e, AwawaLoadError::MalformedAwatismError(_)) => return Err(e),
+            _ => return Err(AwawaLoadError::MalformedAwatismError(self.span(start))),
+        }
+    }
+
+    /// Reads the initial awa every program starts with
+    pub fn read_preamble(&mut self) -> AwawaLoadResult {
+        self.skip_ignored();
+        let start = self.position();
+        match self.read_n(1) {
+            Ok(Some(0)) => return Ok(()),
+            _ => {
+                return Err(AwawaLoadError::MissingInitialAwaError(self.span(start)));
+            }
+        }
+    }
+
+    /// Decodes the awatism with index `index`, returns `None` at the end of the Awatalk
+    pub fn next_awatism(&mut self, index: usize) -> Result<Option<Awatism>, AwawaLoadError> {
+        self.skip_ignored();
+        let start = self.position();
+
+        let res = match self.read_n(5) {
+            Ok(None) => return Ok(None),
+            Ok(Some(code)) => self.fetch_awatism(code, start),
+            Err(e) => Err(e),
+        };
+
+        return res.map(Some).map_err(|mut e| {
+            e.span_mut().index = index;
+            return e;
+        });
+    }
+
+    fn fetch_awatism(&mut self, awatism: u8, start: Position) -> Result<Awatism, AwawaLoadError> {
+        return match awatism {
+            0x0 => Ok(Awatism::Nop),
+            0x1 => Ok(Awatism::Prn),
+            0x2 => Ok(Awatism::Pr1),
+            0x3 => Ok(Awatism::Red),
+            0x4 => Ok(Awatism::R3d),
+            0x5 => {
+                let val = self.read_arg(8, start)?;
+                return Ok(Awatism::Blo(val as i8));
+            }
+            0x6 => {
+                let val = self.read_arg(5, start)?;
+                return Ok(Awatism::Sbm(val));
+            }
+            0x7 => Ok(Awatism::Pop),
+            0x8 => Ok(Awatism::Dpl),
+            0x9 => {
+                let val = self.read_arg(5, start)?;
+                return Ok(Awatism::Srn(val));
+            }
+            0x0A => Ok(Awatism::Mrg),
+            0x0B => Ok(Awatism::Add),
+            0x0C => Ok(Awatism::Sub),
+            0x0D => Ok(Awatism::Mul),
+            0x0E => Ok(Awatism::Div),
+            0x0F => Ok(Awatism::Cnt),
+            0x10 => {
+                let val = self.read_arg(5, start)?;
+                return Ok(Awatism::Lbl(val));
+            }
+            0x11 => {
+                let val = self.read_arg(5, start)?;
+                return Ok(Awatism::Jmp(val));
+            }
+            0x12 => Ok(Awatism::Eql),
+            0x13 => Ok(Awatism::Lss),
+            0x14 => Ok(Awatism::Gr8),
+            0x1F => Ok(Awatism::Trm),
+            a => return Err(AwawaLoadError::UnknownAwatismError(a, self.span(start))),
+        };
+    }
+}
+
+/// Decodes Awatalk into `dst`. Awatisms decoded before an error are left in `dst`.
+pub fn disawassemble(awa: Chars, dst: &mut Vec<Awatism>) -> AwawaLoadResult {
+    let mut reader = AwatalkReader::new(awa);
+    reader.read_preamble()?;
+
+    while let Some(awatism) = reader.next_awatism(dst.len())? {
+        dst.push(awatism);
+    }
+    return Ok(());
+}
@@ -1,4 +1,14 @@
-use std::{collections::VecDeque, fmt::Display};
+use std::{
+    cell::Cell,
+    cmp::Ordering,
+    collections::VecDeque,
+    fmt::{Debug, Display},
+    ops::Deref,
+    rc::Rc,
+};
+
+#[cfg(feature = "bignum")]
+use num_bigint::BigInt;
 
 use crate::{
     awacpu::awascii::awascii,
@@ -10,14 +20,217 @@ pub struct BubbleStack {
     stack: VecDeque<BubbleItem>,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+/// A bubble or Double Bubble.
+///
+/// Double Bubbles can be nested millions of levels deep, so every traversal, including `Drop`,
+/// `PartialEq`, `Debug` and `Display`, walks them without recursion.
+#[derive(Clone)]
 pub enum BubbleItem {
     Bubble(i32),
-    DoubleBubble(VecDeque<BubbleItem>),
+    /// A number that does not fit an `i32`, only created by `Arithmetic::Bignum`
+    #[cfg(feature = "bignum")]
+    BigBubble(BigInt),
+    DoubleBubble(Bubbles),
 }
 
-impl Display for BubbleItem {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+thread_local! {
+    /// Double Bubbles allocated on this thread while `count_double_bubbles` runs, `None` the rest
+    /// of the time so running without a profile does not count
+    static DOUBLE_BUBBLE_ALLOCATIONS: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+/// Runs `f` and returns its result with the number of Double Bubbles it allocated or copied
+pub(crate) fn count_double_bubbles<T>(f: impl FnOnce() -> T) -> (T, u64) {
+    DOUBLE_BUBBLE_ALLOCATIONS.set(Some(0));
+    let res = f();
+    let count = DOUBLE_BUBBLE_ALLOCATIONS.take().unwrap_or_default();
+    return (res, count);
+}
+
+fn count_double_bubble_allocation() {
+    if let Some(count) = DOUBLE_BUBBLE_ALLOCATIONS.get() {
+        DOUBLE_BUBBLE_ALLOCATIONS.set(Some(count + 1));
+    }
+}
+
+/// The contents of a Double Bubble.
+///
+/// Clones share the same bubbles, so `dpl` is O(1). A shared Double Bubble is only copied once
+/// it is modified. The nesting depth is kept up to date as bubbles are added, so checking it is
+/// O(1).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bubbles {
+    bubbles: Rc<VecDeque<BubbleItem>>,
+    depth: usize,
+}
+
+impl Bubbles {
+    fn new(bubbles: VecDeque<BubbleItem>) -> Self {
+        count_double_bubble_allocation();
+        let depth = 1 + bubbles.iter().map(BubbleItem::depth).max().unwrap_or(0);
+        return Self {
+            bubbles: Rc::new(bubbles),
+            depth,
+        };
+    }
+
+    /// Nesting depth, a Double Bubble of plain bubbles has depth 1
+    pub fn depth(&self) -> usize {
+        return self.depth;
+    }
+
+    pub fn push_front(&mut self, bubble: BubbleItem) {
+        self.depth = usize::max(self.depth, bubble.depth() + 1);
+        self.bubbles_mut().push_front(bubble);
+    }
+
+    pub fn push_back(&mut self, bubble: BubbleItem) {
+        self.depth = usize::max(self.depth, bubble.depth() + 1);
+        self.bubbles_mut().push_back(bubble);
+    }
+
+    /// Moves the bubbles of `other` to the back
+    pub fn append(&mut self, other: Bubbles) {
+        self.depth = usize::max(self.depth, other.depth);
+        self.bubbles_mut().append(&mut other.into_inner());
+    }
+
+    /// Returns the bubbles for modification, copying them if they are shared
+    fn bubbles_mut(&mut self) -> &mut VecDeque<BubbleItem> {
+        if Rc::get_mut(&mut self.bubbles).is_none() {
+            count_double_bubble_allocation();
+        }
+        return Rc::make_mut(&mut self.bubbles);
+    }
+
+    /// Returns the bubbles, copying them if they are shared
+    pub fn into_inner(mut self) -> VecDeque<BubbleItem> {
+        return match Rc::get_mut(&mut self.bubbles) {
+            Some(bubbles) => std::mem::take(bubbles),
+            None => {
+                count_double_bubble_allocation();
+                (*self.bubbles).clone()
+            }
+        };
+    }
+}
+
+impl Default for Bubbles {
+    fn default() -> Self {
+        return Self::new(VecDeque::new());
+    }
+}
+
+impl Drop for Bubbles {
+    fn drop(&mut self) {
+        // Move nested Double Bubbles that are not shared onto a heap allocated stack so dropping
+        // them does not recurse
+        let mut pending = match Rc::get_mut(&mut self.bubbles) {
+            Some(bubbles) => std::mem::take(bubbles),
+            None => return,
+        };
+        while let Some(bubble) = pending.pop_back() {
+            if let BubbleItem::DoubleBubble(mut inner) = bubble {
+                if let Some(bubbles) = Rc::get_mut(&mut inner.bubbles) {
+                    pending.append(bubbles);
+                }
+            }
+        }
+    }
+}
+
+impl Deref for Bubbles {
+    type Target = VecDeque<BubbleItem>;
+
+    fn deref(&self) -> &Self::Target {
+        return &self.bubbles;
+    }
+}
+
+impl From<VecDeque<BubbleItem>> for Bubbles {
+    fn from(bubbles: VecDeque<BubbleItem>) -> Self {
+        return Self::new(bubbles);
+    }
+}
+
+impl From<Vec<BubbleItem>> for Bubbles {
+    fn from(bubbles: Vec<BubbleItem>) -> Self {
+        return Self::new(bubbles.into());
+    }
+}
+
+impl FromIterator<BubbleItem> for Bubbles {
+    fn from_iter<T: IntoIterator<Item = BubbleItem>>(iter: T) -> Self {
+        return Self::new(iter.into_iter().collect());
+    }
+}
+
+/// A step of the depth-first walk returned by `BubbleItem::walk`
+#[derive(Clone, Copy, Debug)]
+pub enum Walk<'a> {
+    /// A bubble that is not a Double Bubble
+    Bubble(&'a BubbleItem),
+    /// The start of a Double Bubble, followed by its contents
+    Open,
+    /// The end of the last opened Double Bubble
+    Close,
+}
+
+/// Iterator over a bubble that keeps its position in nested Double Bubbles on the heap
+pub struct WalkIter<'a> {
+    first: Option<&'a BubbleItem>,
+    stack: Vec<std::collections::vec_deque::Iter<'a, BubbleItem>>,
+}
+
+impl<'a> WalkIter<'a> {
+    fn enter(&mut self, bubble: &'a BubbleItem) -> Walk<'a> {
+        match bubble {
+            BubbleItem::DoubleBubble(v) => {
+                self.stack.push(v.iter());
+                return Walk::Open;
+            }
+            _ => return Walk::Bubble(bubble),
+        }
+    }
+}
+
+impl<'a> Iterator for WalkIter<'a> {
+    type Item = Walk<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(bubble) = self.first.take() {
+            return Some(self.enter(bubble));
+        }
+
+        match self.stack.last_mut()?.next() {
+            Some(bubble) => return Some(self.enter(bubble)),
+            None => {
+                self.stack.pop();
+                return Some(Walk::Close);
+            }
+        }
+    }
+}
+
+impl BubbleItem {
+    /// Walks the bubble and everything nested in it depth-first
+    pub fn walk(&self) -> WalkIter<'_> {
+        return WalkIter {
+            first: Some(self),
+            stack: vec![],
+        };
+    }
+
+    /// Nesting depth of Double Bubbles, 0 for a bubble that is not a Double Bubble
+    pub fn depth(&self) -> usize {
+        match self {
+            Self::DoubleBubble(bubbles) => return bubbles.depth(),
+            _ => return 0,
+        }
+    }
+
+    /// Formats a bubble that is not a Double Bubble
+    fn fmt_single(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Bubble(v) => match awascii(*v) {
                 Err(_) => write!(f, "{v}"),
@@ -29,16 +242,89 @@ impl Display for BubbleItem {
                     }
                 }
             },
-            Self::DoubleBubble(v) => {
-                let x = v
-                    .iter()
-                    .map(|x| format!("{x}"))
-                    .collect::<Vec<String>>()
-                    .join(", ");
-
-                write!(f, "({})", x)
+            #[cfg(feature = "bignum")]
+            Self::BigBubble(v) => write!(f, "{v}"),
+            Self::DoubleBubble(_) => unreachable!("Double Bubbles are walked"),
+        }
+    }
+}
+
+impl PartialEq for BubbleItem {
+    fn eq(&self, other: &Self) -> bool {
+        let mut a = self.walk();
+        let mut b = other.walk();
+        loop {
+            match (a.next(), b.next()) {
+                (None, None) => return true,
+                (Some(Walk::Open), Some(Walk::Open)) => (),
+                (Some(Walk::Close), Some(Walk::Close)) => (),
+                (Some(Walk::Bubble(Self::Bubble(x))), Some(Walk::Bubble(Self::Bubble(y)))) => {
+                    if x != y {
+                        return false;
+                    }
+                }
+                #[cfg(feature = "bignum")]
+                (
+                    Some(Walk::Bubble(Self::BigBubble(x))),
+                    Some(Walk::Bubble(Self::BigBubble(y))),
+                ) => {
+                    if x != y {
+                        return false;
+                    }
+                }
+                _ => return false,
+            }
+        }
+    }
+}
+
+impl Display for BubbleItem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut first = true;
+        for step in self.walk() {
+            match step {
+                Walk::Open => {
+                    if !first {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "(")?;
+                    first = true;
+                }
+                Walk::Close => {
+                    write!(f, ")")?;
+                    first = false;
+                }
+                Walk::Bubble(bubble) => {
+                    if !first {
+                        write!(f, ", ")?;
+                    }
+                    bubble.fmt_single(f)?;
+                    first = false;
+                }
+            }
+        }
+        return Ok(());
+    }
+}
+
+impl Debug for BubbleItem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut first = true;
+        for step in self.walk() {
+            if !first && !matches!(step, Walk::Close) {
+                write!(f, ", ")?;
             }
+            match step {
+                Walk::Open => write!(f, "DoubleBubble([")?,
+                Walk::Close => write!(f, "])")?,
+                Walk::Bubble(Self::Bubble(v)) => write!(f, "Bubble({v})")?,
+                #[cfg(feature = "bignum")]
+                Walk::Bubble(Self::BigBubble(v)) => write!(f, "BigBubble({v})")?,
+                Walk::Bubble(Self::DoubleBubble(_)) => unreachable!("Double Bubbles are walked"),
+            }
+            first = matches!(step, Walk::Open);
         }
+        return Ok(());
     }
 }
 
@@ -59,6 +345,25 @@ impl BubbleStack {
         return self.stack.len();
     }
 
+    /// Number of bubbles the top level can hold before it has to grow
+    pub(crate) fn capacity(&self) -> usize {
+        return self.stack.capacity();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.stack.is_empty();
+    }
+
+    /// Iterates over the bubbles, starting at the top
+    pub fn iter(&self) -> std::collections::vec_deque::Iter<'_, BubbleItem> {
+        return self.stack.iter();
+    }
+
+    /// Returns true if the top bubble is nested more than `max` levels deep
+    pub fn top_depth_exceeds(&self, max: usize) -> bool {
+        return self.stack.front().is_some_and(|x| x.depth() > max);
+    }
+
     pub fn pop(&mut self) -> Result<BubbleItem, AwawaError> {
         return self.stack.pop_front().ok_or(AwawaError::BubbleAbyssEmpty);
     }
@@ -103,13 +408,13 @@ impl BubbleStack {
     pub fn pop_bubble(&mut self) -> AwawaResult {
         let top = self.pop()?;
         match top {
-            BubbleItem::Bubble(..) => return Ok(()),
             BubbleItem::DoubleBubble(double) => {
-                let mut double = double;
+                let mut double = double.into_inner();
                 double.append(&mut self.stack);
                 self.stack = double;
                 return Ok(());
             }
+            _ => return Ok(()),
         }
     }
 
@@ -117,8 +422,8 @@ impl BubbleStack {
         self.stack.push_front(val);
     }
 
-    pub fn compare(&mut self, cmp: fn(i32, i32) -> bool) -> Result<bool, AwawaError> {
-        let a = match self.stack.get(0) {
+    pub fn compare(&mut self, cmp: fn(Ordering) -> bool) -> Result<bool, AwawaError> {
+        let a = match self.stack.front() {
             None => return Err(AwawaError::BubbleAbyssEmpty),
             Some(x) => x,
         };
@@ -127,17 +432,20 @@ impl BubbleStack {
             Some(x) => x,
         };
 
-        let a = match a {
-            BubbleItem::DoubleBubble(_) => return Ok(false),
-            BubbleItem::Bubble(x) => x,
-        };
-
-        let b = match b {
-            BubbleItem::DoubleBubble(_) => return Ok(false),
-            BubbleItem::Bubble(x) => x,
+        let ord = match (a, b) {
+            (BubbleItem::DoubleBubble(_), _) | (_, BubbleItem::DoubleBubble(_)) => {
+                return Ok(false)
+            }
+            (BubbleItem::Bubble(a), BubbleItem::Bubble(b)) => a.cmp(b),
+            #[cfg(feature = "bignum")]
+            (BubbleItem::BigBubble(a), BubbleItem::BigBubble(b)) => a.cmp(b),
+            #[cfg(feature = "bignum")]
+            (BubbleItem::BigBubble(a), BubbleItem::Bubble(b)) => a.cmp(&BigInt::from(*b)),
+            #[cfg(feature = "bignum")]
+            (BubbleItem::Bubble(a), BubbleItem::BigBubble(b)) => BigInt::from(*a).cmp(b),
         };
 
-        return Ok(cmp(*a, *b));
+        return Ok(cmp(ord));
     }
 
     pub fn count(&mut self) -> AwawaResult {
@@ -147,10 +455,10 @@ impl BubbleStack {
         };
 
         match val {
-            BubbleItem::Bubble(_) => self.stack.push_front(BubbleItem::Bubble(0)),
             BubbleItem::DoubleBubble(b) => {
                 self.stack.push_front(BubbleItem::Bubble(b.len() as i32))
             }
+            _ => self.stack.push_front(BubbleItem::Bubble(0)),
         };
 
         return Ok(());
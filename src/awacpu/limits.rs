@@ -0,0 +1,18 @@
+use std::time::Duration;
+
+/// Resource limits for running untrusted programs, `None` means unlimited
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum number of awatisms executed
+    pub max_steps: Option<u64>,
+    /// Maximum wall-clock time spent executing awatisms.
+    /// Time spent waiting for `red`/`r3d` input counts as well, but a blocking read is not interrupted.
+    pub timeout: Option<Duration>,
+    /// Maximum number of bubbles on the top level of the Bubble Abyss
+    pub max_abyss_size: Option<usize>,
+    /// Maximum nesting depth of Double Bubbles, a Double Bubble of plain bubbles has depth 1
+    pub max_depth: Option<usize>,
+}
+
+/// The timeout is only checked every this many awatisms to keep the hot loop cheap
+pub(crate) const TIMEOUT_CHECK_INTERVAL: u64 = 1024;
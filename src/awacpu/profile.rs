@@ -0,0 +1,127 @@
+use crate::{awacpu::Awatism, program::Program};
+
+/// Execution counts collected by `AwaCPU` while profiling is enabled
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Profile {
+    /// Number of times each awatism was executed, by instruction index
+    counts: Vec<u64>,
+    /// Outcomes of `eql`, `lss` and `gr8`, by instruction index
+    outcomes: Vec<Outcomes>,
+    max_abyss_size: usize,
+    double_bubble_allocations: u64,
+    abyss_allocations: u64,
+}
+
+/// How often a comparison was true, executing the next awatism, and false, skipping it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Outcomes {
+    pub was_true: u64,
+    pub was_false: u64,
+}
+
+/// The awatisms from a `lbl` up to the next one
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    /// `None` for the awatisms before the first `lbl`
+    pub label: Option<u8>,
+    /// Index of the first awatism
+    pub start: usize,
+    /// Index after the last awatism
+    pub end: usize,
+    /// Number of awatisms executed in the region
+    pub count: u64,
+}
+
+impl Profile {
+    /// Creates an empty profile for a program of `len` awatisms
+    pub fn new(len: usize) -> Profile {
+        return Self {
+            counts: vec![0; len],
+            outcomes: vec![Outcomes::default(); len],
+            max_abyss_size: 0,
+            double_bubble_allocations: 0,
+            abyss_allocations: 0,
+        };
+    }
+
+    /// Counts an execution of the awatism at `ip` with the outcome of a comparison, leaving
+    /// `abyss_size` bubbles in the abyss
+    pub(crate) fn record(&mut self, ip: usize, comparison: Option<bool>, abyss_size: usize) {
+        if let Some(count) = self.counts.get_mut(ip) {
+            *count += 1;
+        }
+        match (comparison, self.outcomes.get_mut(ip)) {
+            (Some(true), Some(outcomes)) => outcomes.was_true += 1,
+            (Some(false), Some(outcomes)) => outcomes.was_false += 1,
+            _ => (),
+        }
+        self.max_abyss_size = self.max_abyss_size.max(abyss_size);
+    }
+
+    /// Counts `double_bubbles` Double Bubbles allocated by an awatism and whether it grew the
+    /// Bubble Abyss
+    pub(crate) fn record_allocations(&mut self, double_bubbles: u64, abyss_grew: bool) {
+        self.double_bubble_allocations += double_bubbles;
+        if abyss_grew {
+            self.abyss_allocations += 1;
+        }
+    }
+
+    /// Number of times each awatism was executed, by instruction index
+    pub fn counts(&self) -> &[u64] {
+        return &self.counts;
+    }
+
+    /// Outcomes of the comparisons, by instruction index. Other awatisms have no outcomes.
+    pub fn outcomes(&self) -> &[Outcomes] {
+        return &self.outcomes;
+    }
+
+    /// Number of awatisms executed
+    pub fn total(&self) -> u64 {
+        return self.counts.iter().sum();
+    }
+
+    /// Most bubbles on the top level of the Bubble Abyss after any awatism
+    pub fn max_abyss_size(&self) -> usize {
+        return self.max_abyss_size;
+    }
+
+    /// Number of Double Bubbles allocated, including copies of shared Double Bubbles that were
+    /// modified
+    pub fn double_bubble_allocations(&self) -> u64 {
+        return self.double_bubble_allocations;
+    }
+
+    /// Number of times the top level of the Bubble Abyss had to grow
+    pub fn abyss_allocations(&self) -> u64 {
+        return self.abyss_allocations;
+    }
+
+    /// Splits `program` at every `lbl` and sums the executions of each part
+    pub fn regions(&self, program: &Program) -> Vec<Region> {
+        let mut regions: Vec<Region> = vec![];
+        for (ip, awatism) in program.awatisms().iter().enumerate() {
+            let count = self.counts.get(ip).copied().unwrap_or_default();
+            match (awatism, regions.last_mut()) {
+                (Awatism::Lbl(lbl), _) => regions.push(Region {
+                    label: Some(*lbl),
+                    start: ip,
+                    end: ip + 1,
+                    count,
+                }),
+                (_, Some(region)) => {
+                    region.end = ip + 1;
+                    region.count += count;
+                }
+                (_, None) => regions.push(Region {
+                    label: None,
+                    start: ip,
+                    end: ip + 1,
+                    count,
+                }),
+            }
+        }
+        return regions;
+    }
+}
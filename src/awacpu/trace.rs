@@ -0,0 +1,143 @@
+use std::{
+    fmt::Write as _,
+    io::{BufWriter, Write},
+};
+
+use crate::{
+    awacpu::{
+        bubblestack::{BubbleItem, BubbleStack, Walk},
+        StepEvent,
+    },
+    errors::{AwawaError, AwawaResult},
+};
+
+/// Writes one JSON object per line for every step of a program.
+///
+/// Executed awatisms look like
+/// `{"step":3,"ip":2,"awatism":"srn","operand":2,"next":3,"abyss":[[2,1]]}`, with `comparison`
+/// added for `eql`, `lss` and `gr8` and `output` for `prn` and `pr1`. The abyss lists its
+/// bubbles from the top, Double Bubbles are arrays. The last line is either
+/// `{"step":9,"ip":8,"halted":true}` or `{"step":4,"ip":3,"error":"..."}`.
+pub struct JsonTrace<'a> {
+    out: BufWriter<Box<dyn Write + 'a>>,
+    line: String,
+}
+
+impl<'a> JsonTrace<'a> {
+    pub fn new<W: Write + 'a>(out: W) -> JsonTrace<'a> {
+        return Self {
+            out: BufWriter::new(Box::new(out)),
+            line: String::new(),
+        };
+    }
+
+    /// Records the result of `AwaCPU::step`, `step` is the number of awatisms executed so far
+    pub fn record(
+        &mut self,
+        step: u64,
+        res: &Result<StepEvent, AwawaError>,
+        ip: usize,
+        abyss: &BubbleStack,
+    ) -> AwawaResult {
+        self.line.clear();
+        let line = &mut self.line;
+        let _ = write!(line, "{{\"step\":{step},");
+        match res {
+            Ok(StepEvent::Executed {
+                awatism,
+                ip_before,
+                ip_after,
+                comparison,
+                output,
+            }) => {
+                let _ = write!(
+                    line,
+                    "\"ip\":{ip_before},\"awatism\":\"{0}\",",
+                    awatism.name()
+                );
+                if let Some(operand) = awatism.operand() {
+                    let _ = write!(line, "\"operand\":{operand},");
+                }
+                let _ = write!(line, "\"next\":{ip_after},");
+                if let Some(comparison) = comparison {
+                    let _ = write!(line, "\"comparison\":{comparison},");
+                }
+                if let Some(output) = output {
+                    line.push_str("\"output\":");
+                    write_string(line, output);
+                    line.push(',');
+                }
+                line.push_str("\"abyss\":");
+                write_abyss(line, abyss);
+            }
+            Ok(StepEvent::Halted { ip }) => {
+                let _ = write!(line, "\"ip\":{ip},\"halted\":true");
+            }
+            Err(e) => {
+                let _ = write!(line, "\"ip\":{ip},\"error\":");
+                write_string(line, &e.to_string());
+            }
+        }
+        line.push_str("}\n");
+
+        return self
+            .out
+            .write_all(line.as_bytes())
+            .map_err(|_| AwawaError::WriteError);
+    }
+
+    pub fn flush(&mut self) -> AwawaResult {
+        return self.out.flush().map_err(|_| AwawaError::WriteError);
+    }
+}
+
+fn write_abyss(out: &mut String, abyss: &BubbleStack) {
+    out.push('[');
+    for (i, bubble) in abyss.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_bubble(out, bubble);
+    }
+    out.push(']');
+}
+
+fn write_bubble(out: &mut String, bubble: &BubbleItem) {
+    let mut first = true;
+    for step in bubble.walk() {
+        if !first && !matches!(step, Walk::Close) {
+            out.push(',');
+        }
+        match step {
+            Walk::Open => out.push('['),
+            Walk::Close => out.push(']'),
+            Walk::Bubble(BubbleItem::Bubble(v)) => {
+                let _ = write!(out, "{v}");
+            }
+            #[cfg(feature = "bignum")]
+            Walk::Bubble(BubbleItem::BigBubble(v)) => {
+                let _ = write!(out, "{v}");
+            }
+            Walk::Bubble(BubbleItem::DoubleBubble(_)) => unreachable!("Double Bubbles are walked"),
+        }
+        first = matches!(step, Walk::Open);
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{0:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
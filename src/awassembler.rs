@@ -1,26 +1,129 @@
-use std::{io::BufRead, str::FromStr};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
 
 use crate::{
     awacpu::{awascii::ord, Awatism},
-    errors::AwawaError,
+    errors::{AwassembleError, AwassembleErrorKind},
+    program::{SourceLocation, SourceMap},
 };
 
-pub fn awassemble<R: BufRead>(src: R, dst: &mut Vec<Awatism>) -> Result<(), AwawaError> {
-    for line in src.lines() {
-        if let Ok(line) = line {
-            let mut res = handle_line(&line)?;
-            dst.append(&mut res);
-        }
-    }
+/// Number of distinct labels addressable by the 5 bit argument of `lbl` and `jmp`
+const LABEL_COUNT: u8 = 32;
 
+/// Awassembles one Awatism per line into `dst`.
+///
+/// Arguments can be numbers, AwaSCII characters in single quotes or names of constants defined
+/// with `.equ NAME value`. `lbl` and `jmp` additionally accept label names, which are assigned
+/// label numbers not used by any numeric `lbl` or `jmp`. Constants and labels may be used before
+/// they are defined.
+///
+/// Macros are defined with `.macro NAME param, ...` up to `.endm` and called like awatisms with
+/// comma separated arguments. Label names defined in a macro are local to each call.
+///
+/// `.include "path"` awassembles another file in place, relative to the including file.
+pub fn awassemble<R: BufRead>(src: R, dst: &mut Vec<Awatism>) -> Result<(), Box<AwassembleError>> {
+    return awassemble_named("<input>", src, dst);
+}
+
+/// Like `awassemble`, but errors refer to the source as `file`
+pub fn awassemble_named<R: BufRead>(
+    file: &str,
+    src: R,
+    dst: &mut Vec<Awatism>,
+) -> Result<(), Box<AwassembleError>> {
+    awassemble_source(file, src, dst)?;
     return Ok(());
 }
 
-fn handle_line(line: &str) -> Result<Vec<Awatism>, AwawaError> {
+/// Like `awassemble_named`, additionally returns where every awatism came from
+pub(crate) fn awassemble_source<R: BufRead>(
+    file: &str,
+    src: R,
+    dst: &mut Vec<Awatism>,
+) -> Result<SourceMap, Box<AwassembleError>> {
+    let mut awassembler = Awassembler {
+        files: vec![String::from(file)],
+        open: fs::canonicalize(file).into_iter().collect(),
+        ..Default::default()
+    };
+    awassembler.read(0, src, &[], 0)?;
+
+    let mut locations = vec![];
+    awassembler.finish(dst, &mut locations)?;
+    let labels = awassembler
+        .labels
+        .into_iter()
+        .map(|(name, lbl)| (lbl, name))
+        .collect();
+    return Ok(SourceMap {
+        files: awassembler.files,
+        locations,
+        labels,
+    });
+}
+
+/// Deepest nesting of macro expansions, so a macro expanding itself is reported
+const MACRO_DEPTH: usize = 64;
+
+/// A non-empty source line split into awatism and arguments.
+/// Columns are 1-based character offsets into `text`.
+struct Line {
+    /// Index into `Awassembler::files`
+    file: usize,
+    number: usize,
+    text: String,
+    awatism: String,
+    awatism_col: usize,
+    args: Option<String>,
+    args_col: usize,
+    /// Macro calls and `.include`s the line came from, innermost first
+    notes: Vec<String>,
+}
+
+/// A macro defined with `.macro NAME params` up to `.endm`
+#[derive(Clone)]
+struct Macro {
+    name: String,
+    /// Index into `Awassembler::files` of the file defining the macro
+    file: usize,
+    params: Vec<String>,
+    /// Line numbers and text of the body
+    body: Vec<(usize, String)>,
+    /// Label names defined in the body, renamed in every expansion
+    locals: Vec<String>,
+}
+
+#[derive(Default)]
+struct Awassembler {
+    /// Names of the awassembled file and every file it includes
+    files: Vec<String>,
+    /// Canonical paths of the files being read, to find include cycles
+    open: Vec<PathBuf>,
+    /// Every line that is not empty, a comment or a directive
+    lines: Vec<Line>,
+    /// Values defined with `.equ`
+    constants: HashMap<String, String>,
+    /// Label numbers assigned to label names
+    labels: HashMap<String, u8>,
+    /// Macros by lowercase name
+    macros: HashMap<String, Macro>,
+    /// The `.macro` line and the macro being defined until `.endm`
+    recording: Option<(Line, Macro)>,
+    /// Number of macro expansions so far, used to name local labels
+    expansions: usize,
+}
+
+/// Splits a source line into awatism and arguments, returns `None` for empty lines and comments
+fn parse_line(file: usize, number: usize, text: String) -> Option<Line> {
     let mut in_q = false;
     let mut in_dq = false;
 
-    let s: String = line
+    let indent = text.chars().take_while(|x| x.is_whitespace()).count();
+    let s: String = text
         .trim_start()
         .chars()
         .take_while(|x| {
@@ -40,21 +143,615 @@ fn handle_line(line: &str) -> Result<Vec<Awatism>, AwawaError> {
         })
         .collect(); // Remove comments
 
-    let (awatism_s, awatism_args) = match s.find(char::is_whitespace) {
-        None => (s.as_str(), None),
-        Some(idx) => (&s[..idx], Some(s[idx..].trim())),
+    let (awatism_s, awatism_args, args_col) = match s.find(char::is_whitespace) {
+        None => (s.as_str(), None, 0),
+        Some(idx) => {
+            let rest = &s[idx..];
+            let ws = rest.chars().take_while(|x| x.is_whitespace()).count();
+            let col = indent + s[..idx].chars().count() + ws + 1;
+            (&s[..idx], Some(rest.trim()).filter(|x| !x.is_empty()), col)
+        }
     };
 
-    if !awatism_s.is_empty() {
-        let awatisms = string_to_awatism(awatism_s, awatism_args)?;
-        return Ok(awatisms);
+    if awatism_s.is_empty() {
+        return None;
+    }
+
+    return Some(Line {
+        file,
+        number,
+        awatism: awatism_s.to_lowercase(),
+        awatism_col: indent + 1,
+        args: awatism_args.map(String::from),
+        args_col,
+        text,
+        notes: vec![],
+    });
+}
+
+impl Awassembler {
+    /// Awassembles the lines of `src`, the file at `file` in `files` included with `notes` from
+    /// a macro expanded `depth` levels deep
+    fn read<R: BufRead>(
+        &mut self,
+        file: usize,
+        src: R,
+        notes: &[String],
+        depth: usize,
+    ) -> Result<(), Box<AwassembleError>> {
+        for (i, text) in src.lines().enumerate() {
+            // Report the line that could not be read instead of assembling a truncated file
+            let text = text.map_err(|e| {
+                return Box::new(AwassembleError {
+                    kind: AwassembleErrorKind::ReadError(self.files[file].clone(), e.to_string()),
+                    file: self.files[file].clone(),
+                    line: i + 1,
+                    column: 1,
+                    len: 0,
+                    snippet: String::new(),
+                    notes: notes.to_vec(),
+                });
+            })?;
+            self.handle_line(file, i + 1, text, notes, depth)?;
+        }
+
+        if let Some((line, mac)) = &self.recording {
+            let e = AwassembleErrorKind::UnterminatedMacroError(mac.name.clone());
+            return Err(self.error(line, false, e));
+        }
+        return Ok(());
+    }
+
+    fn handle_line(
+        &mut self,
+        file: usize,
+        number: usize,
+        text: String,
+        notes: &[String],
+        depth: usize,
+    ) -> Result<(), Box<AwassembleError>> {
+        let mut line = match parse_line(file, number, text) {
+            None => return Ok(()),
+            Some(line) => line,
+        };
+        line.notes = notes.to_vec();
+
+        // `@` is reserved for the local labels of macro expansions
+        if line
+            .args
+            .as_deref()
+            .is_some_and(|args| contains_unquoted(args, '@'))
+        {
+            return Err(self.error(&line, true, AwassembleErrorKind::InvalidArgumentError));
+        }
+
+        if let Some((_, mac)) = &mut self.recording {
+            match line.awatism.as_str() {
+                ".endm" => return self.end_macro(),
+                ".macro" => {
+                    return Err(self.error(&line, false, AwassembleErrorKind::NestedMacroError))
+                }
+                _ => mac.body.push((line.number, line.text)),
+            }
+            return Ok(());
+        }
+
+        return self.handle(line, depth);
+    }
+
+    /// Handles a line of the source or of a macro expanded `depth` levels deep
+    fn handle(&mut self, line: Line, depth: usize) -> Result<(), Box<AwassembleError>> {
+        match line.awatism.as_str() {
+            ".equ" => return self.equ(&line),
+            ".include" => return self.include(&line, depth),
+            ".macro" => return self.define_macro(line),
+            ".endm" => {
+                return Err(self.error(&line, false, AwassembleErrorKind::UnexpectedEndmError))
+            }
+            _ => (),
+        }
+
+        if self.macros.contains_key(&line.awatism) {
+            return self.expand(line, depth);
+        }
+
+        self.lines.push(line);
+        return Ok(());
+    }
+
+    /// Handles `.macro NAME params`, the following lines up to `.endm` are the body
+    fn define_macro(&mut self, line: Line) -> Result<(), Box<AwassembleError>> {
+        let args = match &line.args {
+            None => {
+                return Err(self.error(&line, false, AwassembleErrorKind::MissingArgumentError))
+            }
+            Some(args) => args,
+        };
+        let mut words = args
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|x| !x.is_empty());
+        let name = words.next().unwrap_or_default();
+        let params: Vec<String> = words.map(String::from).collect();
+
+        let is_awatism = !matches!(
+            self.string_to_awatism(&name.to_lowercase(), None),
+            Err(AwassembleErrorKind::UnknownAwatismError(_))
+        );
+        if !is_name(name) || is_awatism || params.iter().any(|x| !is_name(x)) {
+            return Err(self.error(&line, true, AwassembleErrorKind::InvalidArgumentError));
+        }
+        if self.macros.contains_key(&name.to_lowercase()) {
+            let e = AwassembleErrorKind::DuplicateSymbolError(String::from(name));
+            return Err(self.error_at(&line, line.args_col, name.chars().count(), e));
+        }
+        let duplicate = params
+            .iter()
+            .enumerate()
+            .find_map(|(i, x)| params[..i].contains(x).then_some(x));
+        if let Some(param) = duplicate {
+            let e = AwassembleErrorKind::DuplicateSymbolError(param.clone());
+            return Err(self.error(&line, true, e));
+        }
+
+        let mac = Macro {
+            name: String::from(name),
+            file: line.file,
+            params,
+            body: vec![],
+            locals: vec![],
+        };
+        self.recording = Some((line, mac));
+        return Ok(());
+    }
+
+    /// Handles `.endm`, finding the labels local to the macro being defined
+    fn end_macro(&mut self) -> Result<(), Box<AwassembleError>> {
+        let (_, mut mac) = match self.recording.take() {
+            None => return Ok(()),
+            Some(recording) => recording,
+        };
+
+        for (number, text) in &mac.body {
+            let line = match parse_line(mac.file, *number, text.clone()) {
+                None => continue,
+                Some(line) => line,
+            };
+            match (line.awatism.as_str(), line.args) {
+                ("lbl", Some(arg)) if is_name(&arg) && !mac.params.contains(&arg) => {
+                    mac.locals.push(arg)
+                }
+                _ => (),
+            }
+        }
+
+        self.macros.insert(mac.name.to_lowercase(), mac);
+        return Ok(());
+    }
+
+    /// Replaces the call of a macro by its body with the arguments substituted for the
+    /// parameters and local labels renamed to `name@n`
+    fn expand(&mut self, line: Line, depth: usize) -> Result<(), Box<AwassembleError>> {
+        let mac = self.macros[&line.awatism].clone();
+        if depth >= MACRO_DEPTH {
+            let e = AwassembleErrorKind::MacroRecursionError(mac.name);
+            return Err(self.error(&line, false, e));
+        }
+
+        let args = split_args(line.args.as_deref().unwrap_or_default());
+        if args.len() != mac.params.len() {
+            let e = AwassembleErrorKind::MacroArgumentCountError(mac.name, mac.params.len());
+            return Err(self.error(&line, true, e));
+        }
+
+        self.expansions += 1;
+        let mut names: HashMap<&str, String> = HashMap::new();
+        for local in &mac.locals {
+            names.insert(local, format!("{local}@{0}", self.expansions));
+        }
+        for (param, arg) in mac.params.iter().zip(args) {
+            names.insert(param, arg);
+        }
+
+        let note = format!(
+            "in expansion of macro '{0}' at {1}:{2}:{3}",
+            mac.name, self.files[line.file], line.number, line.awatism_col
+        );
+        for (number, text) in &mac.body {
+            let mut expanded = match parse_line(mac.file, *number, substitute(text, &names)) {
+                None => continue,
+                Some(expanded) => expanded,
+            };
+            expanded.notes.push(note.clone());
+            expanded.notes.extend(line.notes.iter().cloned());
+            self.handle(expanded, depth + 1)?;
+        }
+        return Ok(());
+    }
+
+    /// Handles `.include "path"`, awassembling the file in place
+    fn include(&mut self, line: &Line, depth: usize) -> Result<(), Box<AwassembleError>> {
+        let path = match &line.args {
+            None => return Err(self.error(line, false, AwassembleErrorKind::MissingArgumentError)),
+            Some(args) => match args.strip_prefix('"').and_then(|x| x.strip_suffix('"')) {
+                Some(path) if !path.is_empty() => path,
+                _ => return Err(self.error(line, true, AwassembleErrorKind::InvalidArgumentError)),
+            },
+        };
+
+        let dir = Path::new(&self.files[line.file]).parent();
+        let path = dir.unwrap_or(Path::new("")).join(path);
+        let name = path.display().to_string();
+        let (canonical, reader) = File::open(&path)
+            .and_then(|reader| Ok((fs::canonicalize(&path)?, reader)))
+            .map_err(|e| {
+                let e = AwassembleErrorKind::IncludeError(name.clone(), e.to_string());
+                self.error(line, true, e)
+            })?;
+        if self.open.contains(&canonical) {
+            return Err(self.error(line, true, AwassembleErrorKind::IncludeCycleError(name)));
+        }
+
+        let file = match self.files.iter().position(|x| *x == name) {
+            Some(file) => file,
+            None => {
+                self.files.push(name);
+                self.files.len() - 1
+            }
+        };
+        let mut notes = vec![format!(
+            "included from {0}:{1}:{2}",
+            self.files[line.file], line.number, line.awatism_col
+        )];
+        notes.extend(line.notes.iter().cloned());
+
+        self.open.push(canonical);
+        self.read(file, BufReader::new(reader), &notes, depth)?;
+        self.open.pop();
+        return Ok(());
+    }
+
+    /// Handles `.equ NAME value`
+    fn equ(&mut self, line: &Line) -> Result<(), Box<AwassembleError>> {
+        let args = match &line.args {
+            None => return Err(self.error(line, false, AwassembleErrorKind::MissingArgumentError)),
+            Some(args) => args,
+        };
+        let (name, value) = match args.find(char::is_whitespace) {
+            None => return Err(self.error(line, true, AwassembleErrorKind::MissingArgumentError)),
+            Some(idx) => (&args[..idx], args[idx..].trim()),
+        };
+
+        if !is_name(name) {
+            return Err(self.error(line, true, AwassembleErrorKind::InvalidArgumentError));
+        }
+        if self.constants.contains_key(name) {
+            let e = AwassembleErrorKind::DuplicateSymbolError(String::from(name));
+            return Err(self.error_at(line, line.args_col, name.chars().count(), e));
+        }
+
+        self.constants
+            .insert(String::from(name), String::from(value));
+        return Ok(());
+    }
+
+    /// Assigns label numbers to label names and awassembles all lines into `dst`, recording the
+    /// location of each awatism in `locations`
+    fn finish(
+        &mut self,
+        dst: &mut Vec<Awatism>,
+        locations: &mut Vec<SourceLocation>,
+    ) -> Result<(), Box<AwassembleError>> {
+        let mut used = HashSet::new();
+        let mut names: Vec<(&str, &Line)> = vec![];
+        let mut defined = HashSet::new();
+
+        for line in &self.lines {
+            let arg = match (line.awatism.as_str(), &line.args) {
+                ("lbl" | "jmp", Some(arg)) => arg.as_str(),
+                _ => continue,
+            };
+
+            if is_label_name(arg) && !self.constants.contains_key(arg) {
+                if !names.iter().any(|(name, _)| *name == arg) {
+                    names.push((arg, line));
+                }
+                if line.awatism == "lbl" {
+                    defined.insert(arg);
+                }
+            } else {
+                // A numeric jmp without its lbl must stay undefined, not jump to a named label
+                let lbl = self
+                    .number(arg, 0, LABEL_COUNT as i32 - 1)
+                    .map_err(|e| self.error(line, true, e))?;
+                used.insert(lbl as u8);
+            }
+        }
+
+        if let Some((name, line)) = names.iter().find(|(name, _)| !defined.contains(name)) {
+            let e = AwassembleErrorKind::UndefinedSymbolError(String::from(*name));
+            return Err(self.error(line, true, e));
+        }
+
+        let mut free = (0..LABEL_COUNT).filter(|x| !used.contains(x));
+        let mut labels = HashMap::new();
+        for (name, line) in names {
+            let lbl = match free.next() {
+                None => {
+                    return Err(self.error(line, true, AwassembleErrorKind::TooManyLabelsError))
+                }
+                Some(lbl) => lbl,
+            };
+            labels.insert(String::from(name), lbl);
+        }
+        self.labels = labels;
+
+        for line in &self.lines {
+            let mut awatisms = self
+                .string_to_awatism(&line.awatism, line.args.as_deref())
+                .map_err(|e| {
+                    let at_args = !matches!(
+                        e,
+                        AwassembleErrorKind::UnknownAwatismError(_)
+                            | AwassembleErrorKind::MissingArgumentError
+                    );
+                    self.error(line, at_args, e)
+                })?;
+            let location = SourceLocation {
+                file: line.file,
+                line: line.number,
+                column: line.awatism_col,
+            };
+            locations.extend(std::iter::repeat_n(location, awatisms.len()));
+            dst.append(&mut awatisms);
+        }
+
+        return Ok(());
+    }
+
+    /// Creates an error pointing at the arguments of `line` or at its awatism
+    fn error(&self, line: &Line, at_args: bool, kind: AwassembleErrorKind) -> Box<AwassembleError> {
+        return match &line.args {
+            Some(args) if at_args => self.error_at(line, line.args_col, args.chars().count(), kind),
+            _ => self.error_at(line, line.awatism_col, line.awatism.chars().count(), kind),
+        };
+    }
+
+    fn error_at(
+        &self,
+        line: &Line,
+        column: usize,
+        len: usize,
+        kind: AwassembleErrorKind,
+    ) -> Box<AwassembleError> {
+        return Box::new(AwassembleError {
+            kind,
+            file: self.files[line.file].clone(),
+            line: line.number,
+            column,
+            len,
+            snippet: line.text.clone(),
+            notes: line.notes.clone(),
+        });
+    }
+
+    /// Resolves a number, a character in single quotes or a constant to a value in `min..=max`
+    fn number(&self, arg: &str, min: i32, max: i32) -> Result<i32, AwassembleErrorKind> {
+        let mut arg = arg.trim();
+        let mut seen = vec![];
+        while let Some(value) = self.constants.get(arg) {
+            if seen.contains(&arg) {
+                return Err(AwassembleErrorKind::InvalidArgumentError);
+            }
+            seen.push(arg);
+            arg = value.as_str();
+        }
+
+        let val = if let Ok(i) = arg.parse::<i32>() {
+            i
+        } else if let Some(c) = char_literal(arg) {
+            ord(c).ok_or(AwassembleErrorKind::InvalidAwasciiCharError(c))?
+        } else if is_name(arg) {
+            return Err(AwassembleErrorKind::UndefinedSymbolError(String::from(arg)));
+        } else {
+            return Err(AwassembleErrorKind::InvalidArgumentError);
+        };
+
+        if val < min || val > max {
+            return Err(AwassembleErrorKind::InvalidArgumentError);
+        }
+        return Ok(val);
+    }
+
+    /// Resolves the argument of `sbm` and `srn`
+    fn get(&self, args: Option<&str>) -> Result<u8, AwassembleErrorKind> {
+        let arg = args.ok_or(AwassembleErrorKind::MissingArgumentError)?;
+        return Ok(self.number(arg, 0, 31)? as u8);
+    }
+
+    /// Resolves the argument of `lbl` and `jmp`
+    fn get_label(&self, args: Option<&str>) -> Result<u8, AwassembleErrorKind> {
+        let arg = args.ok_or(AwassembleErrorKind::MissingArgumentError)?;
+        if let Some(lbl) = self.labels.get(arg) {
+            return Ok(*lbl);
+        }
+        return Ok(self.number(arg, 0, LABEL_COUNT as i32 - 1)? as u8);
+    }
+
+    fn string_to_awatism(
+        &self,
+        s: &str,
+        args: Option<&str>,
+    ) -> Result<Vec<Awatism>, AwassembleErrorKind> {
+        let res = match s {
+            "nop" => vec![Awatism::Nop],
+            "prn" => vec![Awatism::Prn],
+            "pr1" => vec![Awatism::Pr1],
+            "red" => vec![Awatism::Red],
+            "r3d" => vec![Awatism::R3d],
+            "blo" => {
+                let args = match args {
+                    None => return Err(AwassembleErrorKind::MissingArgumentError),
+                    Some(x) => x.replace("\\n", "\n"),
+                };
+
+                if args.starts_with('\"') && args.ends_with('\"') && args.len() >= 2 {
+                    let awatisms: Result<Vec<Awatism>, AwassembleErrorKind> = args
+                        [1..args.len() - 1]
+                        .chars()
+                        .rev()
+                        .map(|el| match ord(el) {
+                            None => Err(AwassembleErrorKind::InvalidAwasciiCharError(el)),
+                            Some(x) => Ok(Awatism::Blo(x as i8)),
+                        })
+                        .collect();
+                    return awatisms;
+                }
+
+                let val = self.number(&args, i8::MIN.into(), i8::MAX.into())?;
+                vec![Awatism::Blo(val as i8)]
+            }
+            "sbm" => vec![Awatism::Sbm(self.get(args)?)],
+            "pop" => vec![Awatism::Pop],
+            "dpl" => vec![Awatism::Dpl],
+            "srn" => vec![Awatism::Srn(self.get(args)?)],
+            "mrg" => vec![Awatism::Mrg],
+            "add" => vec![Awatism::Add],
+            "sub" => vec![Awatism::Sub],
+            "mul" => vec![Awatism::Mul],
+            "div" => vec![Awatism::Div],
+            "cnt" => vec![Awatism::Cnt],
+            "lbl" => vec![Awatism::Lbl(self.get_label(args)?)],
+            "jmp" => vec![Awatism::Jmp(self.get_label(args)?)],
+            "eql" => vec![Awatism::Eql],
+            "lss" => vec![Awatism::Lss],
+            "gr8" => vec![Awatism::Gr8],
+            "trm" => vec![Awatism::Trm],
+
+            _ => return Err(AwassembleErrorKind::UnknownAwatismError(String::from(s))),
+        };
+        return Ok(res);
+    }
+}
+
+/// Returns true if `s` can be used as the name of a constant or label
+fn is_name(s: &str) -> bool {
+    let mut chars = s.chars();
+    return match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    };
+}
+
+/// Returns true if `s` is a label name, including the `name@n` given to local labels of macros,
+/// which source cannot use
+fn is_label_name(s: &str) -> bool {
+    return match s.split_once('@') {
+        Some((name, n)) => is_name(name) && !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()),
+        None => is_name(s),
+    };
+}
+
+/// Splits the arguments of a macro call at commas outside of quotes
+fn split_args(args: &str) -> Vec<String> {
+    if args.trim().is_empty() {
+        return vec![];
+    }
+
+    let mut in_q = false;
+    let mut in_dq = false;
+    let mut res = vec![String::new()];
+    for c in args.chars() {
+        if c == '\'' && !in_dq {
+            in_q = !in_q;
+        } else if c == '"' && !in_q {
+            in_dq = !in_dq;
+        }
+
+        match res.last_mut() {
+            Some(_) if c == ',' && !in_q && !in_dq => res.push(String::new()),
+            Some(arg) => arg.push(c),
+            None => (),
+        }
+    }
+    return res.iter().map(|x| String::from(x.trim())).collect();
+}
+
+/// Returns true if `s` contains `c` outside of quotes
+fn contains_unquoted(s: &str, c: char) -> bool {
+    let mut in_q = false;
+    let mut in_dq = false;
+    for x in s.chars() {
+        if x == '\'' && !in_dq {
+            in_q = !in_q;
+        } else if x == '"' && !in_q {
+            in_dq = !in_dq;
+        } else if x == c && !in_q && !in_dq {
+            return true;
+        }
+    }
+    return false;
+}
+
+/// Replaces the names in the arguments of `text` outside of quotes and comments by their values
+/// in `names`. The awatism or directive itself is kept as it is.
+fn substitute(text: &str, names: &HashMap<&str, String>) -> String {
+    let mut in_q = false;
+    let mut in_dq = false;
+    let mut in_args = false;
+    let mut res = String::new();
+    let mut word = String::new();
+    let mut chars = text.chars();
+
+    loop {
+        let c = chars.next();
+        if let Some(c) = c.filter(|c| c.is_ascii_alphanumeric() || *c == '_') {
+            word.push(c);
+            continue;
+        }
+
+        match names.get(word.as_str()) {
+            Some(value) if in_args && !in_q && !in_dq && is_name(&word) => res.push_str(value),
+            _ => res.push_str(&word),
+        }
+        in_args |= !word.is_empty();
+        word.clear();
+
+        let c = match c {
+            None => return res,
+            Some(c) => c,
+        };
+        if c == '\'' && !in_dq {
+            in_q = !in_q;
+        } else if c == '"' && !in_q {
+            in_dq = !in_dq;
+        } else if c == '#' && !in_q && !in_dq {
+            // Keep the comment as it is
+            res.push(c);
+            res.extend(chars);
+            return res;
+        }
+        res.push(c);
     }
+}
 
-    return Ok(vec![]);
+/// Returns the character of a single character literal like `'A'` or `'\n'`
+fn char_literal(s: &str) -> Option<char> {
+    let inner = s.strip_prefix('\'')?.strip_suffix('\'')?;
+    if inner == "\\n" {
+        return Some('\n');
+    }
+    let mut chars = inner.chars();
+    return match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    };
 }
 
-pub fn print_awatisms<I>(awas: Vec<Awatism>, out: &mut I) -> std::fmt::Result
+pub fn print_awatisms<A, I>(awas: A, out: &mut I) -> std::fmt::Result
 where
+    A: IntoIterator<Item = Awatism>,
     I: std::fmt::Write,
 {
     write!(out, "awa")?;
@@ -64,7 +761,8 @@ where
     return Ok(());
 }
 
-fn print_awatism<I>(awa: Awatism, out: &mut I) -> std::fmt::Result
+/// Prints the Awatalk of a single awatism, without the `awa` starting every program
+pub fn print_awatism<I>(awa: Awatism, out: &mut I) -> std::fmt::Result
 where
     I: std::fmt::Write,
 {
@@ -95,92 +793,272 @@ where
     return Ok(());
 }
 
-fn get<R>(args: Option<&str>) -> Result<R, AwawaError>
-where
-    R: FromStr,
-{
-    let n = match args {
-        None => return Err(AwawaError::MissingArgumentError),
-        Some(x) => x,
-    };
+#[cfg(test)]
+mod tests {
+    use crate::{awacpu::Awatism, errors::AwassembleErrorKind};
+
+    use super::{awassemble, awassemble_named};
 
-    match n.parse::<R>() {
-        Ok(i) => return Ok(i),
-        Err(_) => return Err(AwawaError::InvalidArgumentError),
+    fn assemble(src: &str) -> Result<Vec<Awatism>, AwassembleErrorKind> {
+        let mut awatisms = vec![];
+        awassemble(src.as_bytes(), &mut awatisms).map_err(|e| e.kind)?;
+        return Ok(awatisms);
     }
-}
 
-fn string_to_awatism(s: &str, args: Option<&str>) -> Result<Vec<Awatism>, AwawaError> {
-    let res = match s.to_lowercase().as_str() {
-        "nop" => vec![Awatism::Nop],
-        "prn" => vec![Awatism::Prn],
-        "pr1" => vec![Awatism::Pr1],
-        "red" => vec![Awatism::Red],
-        "r3d" => vec![Awatism::R3d],
-        "blo" => {
-            let args = match args {
-                None => return Err(AwawaError::MissingArgumentError),
-                Some(x) => x.replace("\\n", "\n"),
-            };
+    #[test]
+    fn blo() -> Result<(), AwassembleErrorKind> {
+        let awatisms = assemble("blo 5\nblo -128\nblo 'y'\nblo '\\n'\nblo \"Aw\"\n")?;
+        let v = vec![
+            Awatism::Blo(5),
+            Awatism::Blo(-128),
+            Awatism::Blo(17),
+            Awatism::Blo(63),
+            Awatism::Blo(3),
+            Awatism::Blo(0),
+        ];
+        assert_eq!(awatisms, v);
 
-            if args.starts_with('\'') && args.ends_with('\'') && args.len() >= 3 {
-                let c = args
-                    .chars()
-                    .nth(1)
-                    .expect("bounds checked so element 1 should always exist");
-                match ord(c) {
-                    None => return Err(AwawaError::InvalidAwasciiCharError(c)),
-                    Some(x) => vec![Awatism::Blo(x as i8)],
-                };
-            }
+        assert!(matches!(
+            assemble("blo 128"),
+            Err(AwassembleErrorKind::InvalidArgumentError)
+        ));
+        assert!(matches!(
+            assemble("blo '?'"),
+            Err(AwassembleErrorKind::InvalidAwasciiCharError('?'))
+        ));
+        return Ok(());
+    }
 
-            if args.starts_with('\"') && args.ends_with('\"') {
-                let awatisms: Result<Vec<Awatism>, AwawaError> = args[1..args.len() - 1]
-                    .chars()
-                    .rev()
-                    .map(|el| match ord(el) {
-                        None => Err(AwawaError::InvalidAwasciiCharError(el)),
-                        Some(x) => Ok(Awatism::Blo(x as i8)),
-                    })
-                    .collect();
-                return awatisms;
-            }
+    #[test]
+    fn named_labels() -> Result<(), AwassembleErrorKind> {
+        let src = "
+            lbl 0
+            jmp end   # forward reference
+            lbl loop
+            jmp loop
+            lbl 1
+            lbl end
+        ";
+        let v = vec![
+            Awatism::Lbl(0),
+            Awatism::Jmp(2),
+            Awatism::Lbl(3),
+            Awatism::Jmp(3),
+            Awatism::Lbl(1),
+            Awatism::Lbl(2),
+        ];
+        assert_eq!(assemble(src)?, v);
 
-            match args.parse::<i8>() {
-                Err(_) => return Err(AwawaError::InvalidArgumentError),
-                Ok(i) => vec![Awatism::Blo(i)],
-            }
-        }
-        "sbm" => {
-            let arg: u8 = get(args)?;
-            vec![Awatism::Sbm(arg)]
-        }
-        "pop" => vec![Awatism::Pop],
-        "dpl" => vec![Awatism::Dpl],
-        "srn" => {
-            let arg: u8 = get(args)?;
-            vec![Awatism::Srn(arg)]
-        }
-        "mrg" => vec![Awatism::Mrg],
-        "add" => vec![Awatism::Add],
-        "sub" => vec![Awatism::Sub],
-        "mul" => vec![Awatism::Mul],
-        "div" => vec![Awatism::Div],
-        "cnt" => vec![Awatism::Cnt],
-        "lbl" => {
-            let arg: u8 = get(args)?;
-            vec![Awatism::Lbl(arg)]
-        }
-        "jmp" => {
-            let arg: u8 = get(args)?;
-            vec![Awatism::Jmp(arg)]
-        }
-        "eql" => vec![Awatism::Eql],
-        "lss" => vec![Awatism::Lss],
-        "gr8" => vec![Awatism::Gr8],
-        "trm" => vec![Awatism::Trm],
-
-        _ => return Err(AwawaError::UnknownAwatismError(String::from(s))),
-    };
-    return Ok(res);
+        assert!(matches!(
+            assemble("jmp nowhere"),
+            Err(AwassembleErrorKind::UndefinedSymbolError(s)) if s == "nowhere"
+        ));
+
+        // Numbers of numeric jumps are not given to named labels
+        let v = vec![Awatism::Jmp(0), Awatism::Lbl(1), Awatism::Jmp(1)];
+        assert_eq!(assemble("jmp 0\nlbl end\njmp end\n")?, v);
+
+        let src: String = (0..33).map(|i| format!("lbl l{i}\n")).collect();
+        assert!(matches!(
+            assemble(&src),
+            Err(AwassembleErrorKind::TooManyLabelsError)
+        ));
+        return Ok(());
+    }
+
+    #[test]
+    fn constants() -> Result<(), AwassembleErrorKind> {
+        let src = "
+            blo COUNT
+            .equ COUNT 5
+            .equ NEWLINE '\\n'
+            .equ TARGET LOOP
+            .equ LOOP 7
+            blo NEWLINE
+            srn COUNT
+            lbl LOOP
+            jmp TARGET
+        ";
+        let v = vec![
+            Awatism::Blo(5),
+            Awatism::Blo(63),
+            Awatism::Srn(5),
+            Awatism::Lbl(7),
+            Awatism::Jmp(7),
+        ];
+        assert_eq!(assemble(src)?, v);
+
+        assert!(matches!(
+            assemble(".equ A 1\n.equ A 2"),
+            Err(AwassembleErrorKind::DuplicateSymbolError(s)) if s == "A"
+        ));
+        assert!(matches!(
+            assemble("srn UNKNOWN"),
+            Err(AwassembleErrorKind::UndefinedSymbolError(s)) if s == "UNKNOWN"
+        ));
+        assert!(matches!(
+            assemble(".equ A 40\nsrn A"),
+            Err(AwassembleErrorKind::InvalidArgumentError)
+        ));
+        return Ok(());
+    }
+
+    #[test]
+    fn error_location() {
+        let src = "blo 1\n\n  # comment\n\tsrn  99 # too deep\n";
+        let mut awatisms = vec![];
+        let e = awassemble_named("deep.awasm", src.as_bytes(), &mut awatisms).unwrap_err();
+        assert!(matches!(e.kind, AwassembleErrorKind::InvalidArgumentError));
+        assert_eq!(e.file, "deep.awasm");
+        assert_eq!((e.line, e.column, e.len), (4, 7, 2));
+        assert_eq!(e.snippet, "\tsrn  99 # too deep");
+        assert_eq!(format!("{e}"), "deep.awasm:4:7: The argument is invalid");
+
+        let e = awassemble("  blo 1\n  jump 3".as_bytes(), &mut awatisms).unwrap_err();
+        assert!(matches!(
+            e.kind,
+            AwassembleErrorKind::UnknownAwatismError(_)
+        ));
+        assert_eq!((e.line, e.column, e.len), (2, 3, 4));
+
+        let e = awassemble(".equ A 1\n.equ  A 2".as_bytes(), &mut awatisms).unwrap_err();
+        assert!(matches!(
+            e.kind,
+            AwassembleErrorKind::DuplicateSymbolError(_)
+        ));
+        assert_eq!((e.line, e.column, e.len), (2, 7, 1));
+    }
+
+    #[test]
+    fn macros() -> Result<(), AwassembleErrorKind> {
+        let src = "
+            .macro push_sum a, b   # comment
+                blo a
+                blo b
+                add
+            .endm
+            .macro say text
+                blo text
+                srn 0
+            .endm
+            push_sum 1, -2
+            PUSH_SUM ',', 'a'
+            say \"a, b\"
+        ";
+        let v = vec![
+            Awatism::Blo(1),
+            Awatism::Blo(-2),
+            Awatism::Add,
+            Awatism::Blo(54),
+            Awatism::Blo(2),
+            Awatism::Add,
+            Awatism::Blo(37),
+            Awatism::Blo(52),
+            Awatism::Blo(54),
+            Awatism::Blo(2),
+            Awatism::Srn(0),
+        ];
+        assert_eq!(assemble(src)?, v);
+
+        // Every call gets its own loop label, loop outside the macro is another label
+        let src = "
+            .macro spin
+                lbl loop
+                jmp loop
+            .endm
+            spin
+            spin
+            lbl loop
+        ";
+        let v = vec![
+            Awatism::Lbl(0),
+            Awatism::Jmp(0),
+            Awatism::Lbl(1),
+            Awatism::Jmp(1),
+            Awatism::Lbl(2),
+        ];
+        assert_eq!(assemble(src)?, v);
+
+        // Macros can call macros defined before the call
+        let src = ".macro one\nblo 1\n.endm\n.macro two\none\none\n.endm\ntwo\n";
+        assert_eq!(assemble(src)?, vec![Awatism::Blo(1), Awatism::Blo(1)]);
+
+        // Parameters and local labels named like awatisms only replace arguments
+        let src = ".macro m pop\npop\nblo pop\nlbl add\nadd\njmp add\n.endm\nm 5\n";
+        let v = vec![
+            Awatism::Pop,
+            Awatism::Blo(5),
+            Awatism::Lbl(0),
+            Awatism::Add,
+            Awatism::Jmp(0),
+        ];
+        assert_eq!(assemble(src)?, v);
+        return Ok(());
+    }
+
+    #[test]
+    fn macro_errors() {
+        assert!(matches!(
+            assemble(".macro m a, b\n.endm\nm 1"),
+            Err(AwassembleErrorKind::MacroArgumentCountError(s, 2)) if s == "m"
+        ));
+        assert!(matches!(
+            assemble(".macro m\nblo 1\n"),
+            Err(AwassembleErrorKind::UnterminatedMacroError(s)) if s == "m"
+        ));
+        assert!(matches!(
+            assemble(".endm"),
+            Err(AwassembleErrorKind::UnexpectedEndmError)
+        ));
+        assert!(matches!(
+            assemble(".macro m\n.macro n\n.endm\n.endm"),
+            Err(AwassembleErrorKind::NestedMacroError)
+        ));
+        assert!(matches!(
+            assemble(".macro m\nm\n.endm\nm"),
+            Err(AwassembleErrorKind::MacroRecursionError(s)) if s == "m"
+        ));
+        assert!(matches!(
+            assemble(".macro add\n.endm"),
+            Err(AwassembleErrorKind::InvalidArgumentError)
+        ));
+        assert!(matches!(
+            assemble(".macro m a, a\n.endm"),
+            Err(AwassembleErrorKind::DuplicateSymbolError(s)) if s == "a"
+        ));
+        // Names like the local labels of expansions cannot be written in source
+        assert!(matches!(
+            assemble(".macro m\nlbl a\n.endm\nm\njmp a@1"),
+            Err(AwassembleErrorKind::InvalidArgumentError)
+        ));
+        assert!(assemble("blo 1 # a@b").is_ok());
+
+        let src = ".macro twice x\n  blo x\n  srn x\n.endm\n\n  twice 40\n";
+        let mut awatisms = vec![];
+        let e = awassemble_named("twice.awasm", src.as_bytes(), &mut awatisms).unwrap_err();
+        assert!(matches!(e.kind, AwassembleErrorKind::InvalidArgumentError));
+        assert_eq!((e.line, e.column, e.len), (3, 7, 2));
+        assert_eq!(e.snippet, "  srn 40");
+        assert_eq!(
+            e.notes,
+            ["in expansion of macro 'twice' at twice.awasm:6:3"]
+        );
+    }
+
+    #[test]
+    fn include_errors() {
+        assert!(matches!(
+            assemble(".include"),
+            Err(AwassembleErrorKind::MissingArgumentError)
+        ));
+        assert!(matches!(
+            assemble(".include lib.awasm"),
+            Err(AwassembleErrorKind::InvalidArgumentError)
+        ));
+
+        let mut awatisms = vec![];
+        let e = awassemble(&b"blo 1\nblo \xff\nblo 2\n"[..], &mut awatisms).unwrap_err();
+        assert!(matches!(e.kind, AwassembleErrorKind::ReadError(s, _) if s == "<input>"));
+        assert_eq!(e.line, 2);
+    }
 }
@@ -0,0 +1,257 @@
+use std::fmt::Display;
+
+use crate::{awacpu::Awatism, program::Program};
+
+/// An awatism that can find fewer bubbles in the Bubble Abyss than it needs, found by `check`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StackIssue {
+    /// Instruction index of the awatism
+    pub ip: usize,
+    pub awatism: Awatism,
+    /// Number of bubbles the awatism needs
+    pub needed: usize,
+    /// Fewest bubbles the abyss can hold when the awatism is reached
+    pub min: usize,
+    /// Most bubbles the abyss can hold when the awatism is reached, `None` if unknown
+    pub max: Option<usize>,
+}
+
+impl StackIssue {
+    /// Returns true if the awatism fails whenever it is reached
+    pub fn always_fails(&self) -> bool {
+        return self.max.is_some_and(|max| max < self.needed);
+    }
+}
+
+impl Display for StackIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let needed = match self.needed {
+            1 => String::from("1 bubble"),
+            n => format!("{n} bubbles"),
+        };
+        return match self.max {
+            Some(max) if self.always_fails() => write!(
+                f,
+                "[{0}] {1} needs {needed} but the Bubble Abyss holds at most {max}",
+                self.ip, self.awatism
+            ),
+            _ => write!(
+                f,
+                "[{0}] {1} needs {needed} but the Bubble Abyss may hold only {2}",
+                self.ip, self.awatism, self.min
+            ),
+        };
+    }
+}
+
+/// Bounds on the number of bubbles in the Bubble Abyss
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Depth {
+    min: usize,
+    /// `None` if there is no known bound
+    max: Option<usize>,
+}
+
+impl Depth {
+    /// Combines the depths of two paths. With `widen` an upper bound that grows is dropped, so
+    /// loops that keep pushing bubbles are analysed in a few rounds.
+    fn join(self, other: Depth, widen: bool) -> Depth {
+        let max = match (self.max, other.max) {
+            (Some(a), Some(b)) if b <= a || !widen => Some(a.max(b)),
+            _ => None,
+        };
+        return Depth {
+            min: self.min.min(other.min),
+            max,
+        };
+    }
+}
+
+/// Bubbles an awatism needs, takes and puts back into the Bubble Abyss
+struct Effect {
+    needs: usize,
+    pops: usize,
+    pushes: usize,
+}
+
+fn effect(awatism: Awatism) -> Effect {
+    let (needs, pops, pushes) = match awatism {
+        Awatism::Prn | Awatism::Pr1 | Awatism::Pop => (1, 1, 0),
+        Awatism::Red | Awatism::R3d | Awatism::Blo(_) => (0, 0, 1),
+        // sbm 0 moves the top bubble to the bottom, sbm n moves it below the next n bubbles
+        Awatism::Sbm(0) => (1, 0, 0),
+        Awatism::Sbm(n) => (n as usize + 1, 0, 0),
+        Awatism::Dpl | Awatism::Cnt => (1, 0, 1),
+        Awatism::Srn(n) => (n as usize, n as usize, 1),
+        Awatism::Mrg | Awatism::Add | Awatism::Sub | Awatism::Mul | Awatism::Div => (2, 2, 1),
+        Awatism::Eql | Awatism::Lss | Awatism::Gr8 => (2, 0, 0),
+        Awatism::Nop | Awatism::Lbl(_) | Awatism::Jmp(_) | Awatism::Trm => (0, 0, 0),
+    };
+    return Effect {
+        needs,
+        pops,
+        pushes,
+    };
+}
+
+/// Finds awatisms that may run out of bubbles by following every path through `program`,
+/// including both outcomes of each comparison.
+///
+/// The check is conservative: popping a Double Bubble puts back an unknown number of bubbles, so
+/// programs relying on that can be reported even though they never fail.
+pub fn check(program: &Program) -> Vec<StackIssue> {
+    let awatisms = program.awatisms();
+    let mut depths: Vec<Option<Depth>> = vec![None; awatisms.len()];
+    let mut pending = vec![];
+    if !awatisms.is_empty() {
+        depths[0] = Some(Depth {
+            min: 0,
+            max: Some(0),
+        });
+        pending.push(0);
+    }
+
+    while let Some(ip) = pending.pop() {
+        let depth = match depths[ip] {
+            None => continue,
+            Some(depth) => depth,
+        };
+        let awatism = awatisms[ip];
+        let effect = effect(awatism);
+
+        // Execution stops at an awatism that always fails
+        if depth.max.is_some_and(|max| max < effect.needs) {
+            continue;
+        }
+        // Paths that get past an awatism had enough bubbles for it
+        let min = depth.min.max(effect.needs) - effect.pops + effect.pushes;
+        let max = match awatism {
+            // A popped Double Bubble puts its bubbles back
+            Awatism::Pop => None,
+            _ => depth.max.map(|max| max - effect.pops + effect.pushes),
+        };
+        let after = Depth { min, max };
+
+        for next in program.successors(ip) {
+            if next >= awatisms.len() {
+                continue;
+            }
+            // Every loop jumps backwards
+            let joined = match depths[next] {
+                None => after,
+                Some(depth) => depth.join(after, next <= ip),
+            };
+            if depths[next] != Some(joined) {
+                depths[next] = Some(joined);
+                pending.push(next);
+            }
+        }
+    }
+
+    let mut issues = vec![];
+    for (ip, depth) in depths.iter().enumerate() {
+        let (awatism, depth) = match depth {
+            None => continue,
+            Some(depth) => (awatisms[ip], depth),
+        };
+        let needed = effect(awatism).needs;
+        if depth.min < needed {
+            issues.push(StackIssue {
+                ip,
+                awatism,
+                needed,
+                min: depth.min,
+                max: depth.max,
+            });
+        }
+    }
+    return issues;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, StackIssue};
+    use crate::{awacpu::Awatism, program::Program};
+
+    fn check_awasm(src: &str) -> Vec<StackIssue> {
+        let program = Program::from_awasm("check.awasm", src.as_bytes()).unwrap();
+        return check(&program);
+    }
+
+    #[test]
+    fn straight_line() {
+        assert_eq!(check_awasm("blo 1\nblo 2\nadd\npr1\n"), []);
+
+        let issues = check_awasm("blo 1\nadd\n");
+        assert_eq!(
+            issues,
+            [StackIssue {
+                ip: 1,
+                awatism: Awatism::Add,
+                needed: 2,
+                min: 1,
+                max: Some(1),
+            }]
+        );
+        assert!(issues[0].always_fails());
+
+        // Execution stops at the failing awatism
+        assert_eq!(check_awasm("prn\nprn\n").len(), 1);
+    }
+
+    #[test]
+    fn srn_sbm() {
+        assert_eq!(check_awasm("blo 1\nblo 2\nsrn 2\nsbm 0\n"), []);
+        assert_eq!(check_awasm("blo 1\nblo 2\nsbm 1\n"), []);
+
+        let issues = check_awasm("blo 1\nblo 2\nsbm 2\n");
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].needed, 3);
+
+        let issues = check_awasm("blo 1\nsrn 2\n");
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].awatism, Awatism::Srn(2));
+    }
+
+    #[test]
+    fn branches() {
+        // Only the path skipping blo 2 underflows
+        let src = "blo 1\nblo 1\neql\nblo 2\nadd\nadd\n";
+        let issues = check_awasm(src);
+        assert_eq!(
+            issues,
+            [StackIssue {
+                ip: 5,
+                awatism: Awatism::Add,
+                needed: 2,
+                min: 1,
+                max: Some(2),
+            }]
+        );
+        assert!(!issues[0].always_fails());
+    }
+
+    #[test]
+    fn loops() {
+        // Pushing in a loop widens the upper bound
+        assert_eq!(check_awasm("lbl 0\nblo 1\njmp 0\n"), []);
+
+        // Popping in a loop eventually underflows
+        let issues = check_awasm("blo 1\nblo 2\nlbl 0\npr1\njmp 0\n");
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].ip, 3);
+        assert_eq!(issues[0].min, 0);
+
+        // Code after trm and jumps is never reached
+        assert_eq!(check_awasm("trm\nprn\n"), []);
+        assert_eq!(check_awasm("jmp 0\nprn\nlbl 0\n"), []);
+    }
+
+    #[test]
+    fn pop() {
+        // Popping a Double Bubble puts back an unknown number of bubbles
+        let issues = check_awasm("blo 1\nblo 2\nsrn 2\npop\npr1\n");
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].max, None);
+    }
+}
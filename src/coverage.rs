@@ -0,0 +1,231 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+};
+
+use awatistic::{Awatism, Profile, Program};
+
+/// Source file of the awatism at `ip`: its awasm file if the program has a source map, otherwise
+/// the Awatalk called `name`
+fn source_file<'a>(name: &'a str, program: &'a Program, ip: usize) -> &'a str {
+    return program
+        .source()
+        .and_then(|source| source.file(ip))
+        .unwrap_or(name);
+}
+
+/// The source files with the instruction indices of their awatisms, the awassembled file first
+fn source_files<'a>(name: &'a str, program: &'a Program) -> Vec<(&'a str, Vec<usize>)> {
+    let main = match program.source().and_then(|source| source.files.first()) {
+        Some(file) => file.as_str(),
+        None => name,
+    };
+    let mut files: Vec<(&str, Vec<usize>)> = vec![(main, vec![])];
+    for ip in 0..program.len() {
+        let file = source_file(name, program, ip);
+        match files.iter_mut().find(|(f, _)| *f == file) {
+            Some((_, ips)) => ips.push(ip),
+            None => files.push((file, vec![ip])),
+        }
+    }
+    return files;
+}
+
+/// Source line of the awatism at `ip`. Awatalk has no meaningful lines, so without a source map
+/// this is the line `disawassemble` prints the awatism on.
+fn source_line(program: &Program, ip: usize) -> usize {
+    return program
+        .source()
+        .and_then(|source| source.line(ip))
+        .unwrap_or(ip + 1);
+}
+
+fn is_comparison(awatism: Awatism) -> bool {
+    return matches!(awatism, Awatism::Eql | Awatism::Lss | Awatism::Gr8);
+}
+
+/// Writes the coverage in LCOV format, one record per source file. Each comparison is a branch
+/// with two outcomes, true executing the next awatism and false skipping it.
+pub fn write_lcov<W: Write>(
+    out: &mut W,
+    name: &str,
+    program: &Program,
+    profile: &Profile,
+) -> io::Result<()> {
+    for (file, ips) in source_files(name, program) {
+        write_record(out, file, &ips, program, profile)?;
+    }
+    return Ok(());
+}
+
+/// Writes the LCOV record of the awatisms at `ips`, which all come from `file`
+fn write_record<W: Write>(
+    out: &mut W,
+    file: &str,
+    ips: &[usize],
+    program: &Program,
+    profile: &Profile,
+) -> io::Result<()> {
+    writeln!(out, "TN:")?;
+    writeln!(out, "SF:{file}")?;
+
+    let mut branches = 0;
+    let mut branches_hit = 0;
+    for &ip in ips {
+        let awatism = program.awatisms()[ip];
+        if !is_comparison(awatism) {
+            continue;
+        }
+        let line = source_line(program, ip);
+        let outcomes = profile.outcomes()[ip];
+        for (branch, taken) in [outcomes.was_true, outcomes.was_false].iter().enumerate() {
+            branches += 1;
+            if *taken > 0 {
+                branches_hit += 1;
+            }
+            if profile.counts()[ip] == 0 {
+                writeln!(out, "BRDA:{line},{ip},{branch},-")?;
+            } else {
+                writeln!(out, "BRDA:{line},{ip},{branch},{taken}")?;
+            }
+        }
+    }
+    writeln!(out, "BRF:{branches}")?;
+    writeln!(out, "BRH:{branches_hit}")?;
+
+    // A line awassembled to several awatisms counts as often as its most executed one
+    let mut lines = BTreeMap::new();
+    for &ip in ips {
+        let line = lines.entry(source_line(program, ip)).or_insert(0);
+        *line = u64::max(*line, profile.counts()[ip]);
+    }
+    for (line, count) in &lines {
+        writeln!(out, "DA:{line},{count}")?;
+    }
+    writeln!(out, "LF:{0}", lines.len())?;
+    writeln!(out, "LH:{0}", lines.values().filter(|x| **x > 0).count())?;
+    writeln!(out, "end_of_record")?;
+    return Ok(());
+}
+
+/// Prints how many awatisms and comparison outcomes were covered and lists the ones that were not
+pub fn print_summary<W: Write>(
+    out: &mut W,
+    name: &str,
+    program: &Program,
+    profile: &Profile,
+) -> io::Result<()> {
+    let awatisms = program.awatisms();
+    let counts = profile.counts();
+    let percent = |hit: usize, total: usize| -> f64 {
+        if total == 0 {
+            return 100.0;
+        }
+        return hit as f64 * 100.0 / total as f64;
+    };
+
+    let executed = counts.iter().filter(|x| **x > 0).count();
+    let mut outcomes = 0;
+    let mut outcomes_hit = 0;
+    let mut missed = vec![];
+    for (ip, awatism) in awatisms.iter().enumerate() {
+        let file = source_file(name, program, ip);
+        let line = source_line(program, ip);
+        if counts[ip] == 0 {
+            missed.push(format!("{file}:{line} [{ip}] {awatism} never executed"));
+        }
+        if !is_comparison(*awatism) {
+            continue;
+        }
+
+        let o = profile.outcomes()[ip];
+        outcomes += 2;
+        outcomes_hit += usize::from(o.was_true > 0) + usize::from(o.was_false > 0);
+        if counts[ip] > 0 && o.was_true == 0 {
+            missed.push(format!("{file}:{line} [{ip}] {awatism} never true"));
+        }
+        if counts[ip] > 0 && o.was_false == 0 {
+            missed.push(format!("{file}:{line} [{ip}] {awatism} never false"));
+        }
+    }
+
+    writeln!(out, "Coverage of {0}:", source_files(name, program)[0].0)?;
+    writeln!(
+        out,
+        "  Awatisms executed: {executed} of {0} ({1:.2}%)",
+        awatisms.len(),
+        percent(executed, awatisms.len())
+    )?;
+    writeln!(
+        out,
+        "  Comparison outcomes: {outcomes_hit} of {outcomes} ({0:.2}%)",
+        percent(outcomes_hit, outcomes)
+    )?;
+    for m in missed {
+        writeln!(out, "{m}")?;
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use awatistic::{AwaCPU, AwawaError, Profile, Program};
+
+    use super::{print_summary, write_lcov};
+
+    /// Compares once, always true, and never reaches the comparison on the last line
+    const BRANCH: &str = "blo 1\nblo 1\neql\ntrm\ngr8\n";
+
+    fn profile(program: &Program) -> Profile {
+        let mut cpu = AwaCPU::with_io(program, 0, io::empty(), io::sink());
+        cpu.enable_profile();
+        assert!(matches!(cpu.run(), Err(AwawaError::EndOfProgramError())));
+        return cpu.get_profile().unwrap().clone();
+    }
+
+    #[test]
+    fn lcov() {
+        let program = Program::from_awasm("branch.awasm", BRANCH.as_bytes()).unwrap();
+        let mut out = vec![];
+        write_lcov(&mut out, "branch.awasm", &program, &profile(&program)).unwrap();
+        let expected = [
+            "TN:",
+            "SF:branch.awasm",
+            "BRDA:3,2,0,1",
+            "BRDA:3,2,1,0",
+            "BRDA:5,4,0,-",
+            "BRDA:5,4,1,-",
+            "BRF:4",
+            "BRH:1",
+            "DA:1,1",
+            "DA:2,1",
+            "DA:3,1",
+            "DA:4,1",
+            "DA:5,0",
+            "LF:5",
+            "LH:4",
+            "end_of_record",
+        ];
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            expected.map(|l| return format!("{l}\n")).concat()
+        );
+    }
+
+    #[test]
+    fn summary() {
+        let program = Program::from_awasm("branch.awasm", BRANCH.as_bytes()).unwrap();
+        let mut out = vec![];
+        print_summary(&mut out, "branch.awasm", &program, &profile(&program)).unwrap();
+        let expected = "\
+Coverage of branch.awasm:
+  Awatisms executed: 4 of 5 (80.00%)
+  Comparison outcomes: 1 of 4 (25.00%)
+branch.awasm:3 [2] eql never false
+branch.awasm:5 [4] gr8 never executed
+";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+}
@@ -0,0 +1,395 @@
+use std::{
+    collections::BTreeSet,
+    io::{self, BufRead, BufReader, Write},
+    process::ExitCode,
+};
+
+use awatistic::{Arithmetic, AwaCPU, AwawaError, Program, StepEvent};
+
+const HELP: &str = "\
+Commands:
+  s, step [n]          Execute the next n awatisms (default 1)
+  c, continue          Run until a breakpoint is hit or the program ends
+  f, finish            Run until the program ends, ignoring breakpoints
+  b, break <ip>        Set a breakpoint at instruction index ip
+  b, break lbl <n>     Set a breakpoint at label n
+  d, delete <ip>       Delete the breakpoint at instruction index ip
+  i, info              List breakpoints
+  l, list [n]          Show n awatisms around the current instruction (default 5)
+  a, abyss             Print the Bubble Abyss
+  h, help              Show this help
+  q, quit              Exit the debugger
+An empty line repeats the previous command.";
+
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    Step(usize),
+    Continue,
+    Finish,
+    Break(usize),
+    BreakLabel(u8),
+    Delete(usize),
+    Info,
+    List(usize),
+    Abyss,
+    Help,
+    Quit,
+}
+
+struct Debugger<'a> {
+    cpu: AwaCPU<'a>,
+    breakpoints: BTreeSet<usize>,
+    running: bool,
+    /// The runtime error that stopped the program
+    error: Option<AwawaError>,
+    /// Debugger commands
+    commands: Box<dyn BufRead + 'a>,
+    /// Debugger messages, the program writes to the output of `cpu`
+    out: Box<dyn Write + 'a>,
+}
+
+pub fn debug(name: &str, awa: &str, arith: Arithmetic) -> ExitCode {
+    let program = match Program::from_awatalk(awa) {
+        Err(e) => {
+            crate::print_load_error(name, awa, &e);
+            return ExitCode::from(4);
+        }
+        Ok(program) => program,
+    };
+    // Debugger commands and program input share stdin, so neither must read ahead
+    let input = BufReader::with_capacity(1, io::stdin());
+    let mut cpu = AwaCPU::with_io(&program, 0, input, io::stdout());
+    cpu.set_arithmetic(arith);
+
+    let commands = BufReader::with_capacity(1, io::stdin());
+    let mut debugger = Debugger::new(cpu, commands, io::stdout());
+    if let Err(e) = debugger.run() {
+        eprintln!("Error writing debugger output: {e}");
+    }
+    return ExitCode::from(debugger.exit_code());
+}
+
+impl<'a> Debugger<'a> {
+    fn new<R, W>(cpu: AwaCPU<'a>, commands: R, out: W) -> Debugger<'a>
+    where
+        R: BufRead + 'a,
+        W: Write + 'a,
+    {
+        return Self {
+            cpu,
+            breakpoints: BTreeSet::new(),
+            running: true,
+            error: None,
+            commands: Box::new(commands),
+            out: Box::new(out),
+        };
+    }
+
+    /// Reads and executes commands until `quit` or the end of the commands
+    fn run(&mut self) -> io::Result<()> {
+        writeln!(
+            self.out,
+            "Loaded {0} awatisms. Type 'help' for a list of commands.",
+            self.cpu.get_program().len()
+        )?;
+        self.show_location()?;
+        return self.repl();
+    }
+
+    /// Exit code of `run` for the runtime error that stopped the program, 0 without one
+    fn exit_code(&self) -> u8 {
+        return match &self.error {
+            Some(e) => crate::runtime_exit_code(e),
+            None => 0,
+        };
+    }
+
+    fn repl(&mut self) -> io::Result<()> {
+        let mut last = String::new();
+
+        loop {
+            write!(self.out, "(awa) ")?;
+            self.out.flush()?;
+
+            let mut line = String::new();
+            match self.commands.read_line(&mut line) {
+                Ok(0) | Err(_) => return Ok(()),
+                _ => (),
+            }
+
+            let line = line.trim();
+            if !line.is_empty() {
+                last = line.to_string();
+            }
+
+            let cmd = match parse_command(&last) {
+                Ok(cmd) => cmd,
+                Err(e) => {
+                    writeln!(self.out, "{e}")?;
+                    continue;
+                }
+            };
+
+            match cmd {
+                Command::Step(n) => {
+                    for _ in 0..n {
+                        if !self.step(true)? {
+                            break;
+                        }
+                    }
+                    self.show_location()?;
+                }
+                Command::Continue => {
+                    while self.step(false)? {
+                        if self.breakpoints.contains(&self.cpu.get_ip()) {
+                            writeln!(self.out, "Breakpoint at [{0}]", self.cpu.get_ip())?;
+                            break;
+                        }
+                    }
+                    self.show_location()?;
+                }
+                Command::Finish => while self.step(false)? {},
+                Command::Break(ip) => self.set_breakpoint(ip)?,
+                Command::BreakLabel(lbl) => match self.cpu.get_label(lbl) {
+                    None => writeln!(self.out, "Label {lbl} does not exist")?,
+                    Some(ip) => self.set_breakpoint(ip)?,
+                },
+                Command::Delete(ip) => {
+                    if !self.breakpoints.remove(&ip) {
+                        writeln!(self.out, "No breakpoint at [{ip}]")?;
+                    }
+                }
+                Command::Info => {
+                    if self.breakpoints.is_empty() {
+                        writeln!(self.out, "No breakpoints")?;
+                    }
+                    for ip in &self.breakpoints {
+                        writeln!(
+                            self.out,
+                            "Breakpoint at [{ip}] {0}",
+                            self.cpu.get_program().awatisms()[*ip]
+                        )?;
+                    }
+                }
+                Command::List(n) => self.list(n)?,
+                Command::Abyss => writeln!(self.out, "{0}", self.cpu.get_bubble_abyss())?,
+                Command::Help => writeln!(self.out, "{HELP}")?,
+                Command::Quit => return Ok(()),
+            }
+        }
+    }
+
+    /// Executes a single awatism, returns false once the program is no longer running.
+    /// With `trace` the executed awatism is printed.
+    fn step(&mut self, trace: bool) -> io::Result<bool> {
+        if !self.running {
+            writeln!(self.out, "The program is not running")?;
+            return Ok(false);
+        }
+
+        let ip = self.cpu.get_ip();
+        let res = self.cpu.step();
+        self.out.flush()?;
+        match res {
+            Ok(StepEvent::Executed {
+                awatism,
+                comparison,
+                output,
+                ..
+            }) => {
+                if !trace {
+                    return Ok(true);
+                }
+                if output.is_some_and(|s| !s.ends_with('\n')) {
+                    writeln!(self.out)?;
+                }
+                match comparison {
+                    Some(true) => writeln!(self.out, "[{ip}] {awatism} (true - exec next)")?,
+                    Some(false) => writeln!(self.out, "[{ip}] {awatism} (false - skip next)")?,
+                    None => writeln!(self.out, "[{ip}] {awatism}")?,
+                }
+                return Ok(true);
+            }
+            Ok(StepEvent::Halted { .. }) => writeln!(self.out, "Program ended.")?,
+            Err(e) => {
+                writeln!(self.out, "Error executing instruction {ip}: {e}")?;
+                self.error = Some(e);
+            }
+        }
+        self.running = false;
+        return Ok(false);
+    }
+
+    fn set_breakpoint(&mut self, ip: usize) -> io::Result<()> {
+        match self.cpu.get_program().awatisms().get(ip) {
+            None => writeln!(self.out, "Instruction {ip} does not exist")?,
+            Some(awatism) => {
+                self.breakpoints.insert(ip);
+                writeln!(self.out, "Breakpoint at [{ip}] {awatism}")?;
+            }
+        }
+        return Ok(());
+    }
+
+    fn show_location(&mut self) -> io::Result<()> {
+        if !self.running {
+            return Ok(());
+        }
+        self.out.flush()?;
+        let ip = self.cpu.get_ip();
+        match self.cpu.get_program().awatisms().get(ip) {
+            None => writeln!(self.out, "-> [{ip}] <end of program>")?,
+            Some(awatism) => writeln!(self.out, "-> [{ip}] {awatism}")?,
+        }
+        return Ok(());
+    }
+
+    fn list(&mut self, n: usize) -> io::Result<()> {
+        let ip = self.cpu.get_ip();
+        let program = self.cpu.get_program().awatisms();
+        let start = ip.saturating_sub(n / 2);
+        let end = usize::min(start + n.max(1), program.len());
+
+        for (i, awatism) in program.iter().enumerate().take(end).skip(start) {
+            let marker = if i == ip && self.running { "->" } else { "  " };
+            let brk = if self.breakpoints.contains(&i) {
+                "*"
+            } else {
+                " "
+            };
+            writeln!(self.out, "{marker}{brk}[{i}] {awatism}")?;
+        }
+        return Ok(());
+    }
+}
+
+fn parse_command(line: &str) -> Result<Command, String> {
+    let mut words = line.split_whitespace();
+    let cmd = match words.next() {
+        None => return Err(String::from("Type 'help' for a list of commands")),
+        Some(cmd) => cmd,
+    };
+    let args: Vec<&str> = words.collect();
+
+    let res = match (cmd, args.as_slice()) {
+        ("s" | "step", []) => Command::Step(1),
+        ("s" | "step", [n]) => Command::Step(parse_number(n)?),
+        ("c" | "continue", []) => Command::Continue,
+        ("f" | "finish", []) => Command::Finish,
+        ("b" | "break", ["lbl", n]) => Command::BreakLabel(parse_number(n)?),
+        ("b" | "break", [ip]) => Command::Break(parse_number(ip)?),
+        ("d" | "delete", [ip]) => Command::Delete(parse_number(ip)?),
+        ("i" | "info", []) => Command::Info,
+        ("l" | "list", []) => Command::List(5),
+        ("l" | "list", [n]) => Command::List(parse_number(n)?),
+        ("a" | "abyss", []) => Command::Abyss,
+        ("h" | "help", []) => Command::Help,
+        ("q" | "quit", []) => Command::Quit,
+        _ => {
+            return Err(format!(
+                "Invalid command '{line}', type 'help' for a list of commands"
+            ))
+        }
+    };
+    return Ok(res);
+}
+
+fn parse_number<T: std::str::FromStr>(s: &str) -> Result<T, String> {
+    return s
+        .parse::<T>()
+        .map_err(|_| format!("'{s}' is not a valid number"));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use awatistic::{AwaCPU, Program};
+
+    use super::{parse_command, Command, Debugger};
+
+    /// Runs the debugger on `src` with the commands in `script`. Returns the debugger messages,
+    /// the program output and the exit code.
+    fn debug_script(src: &str, script: &str) -> (String, String, u8) {
+        let program = Program::from_awasm("debug.awasm", src.as_bytes()).unwrap();
+        let mut messages = vec![];
+        let mut output = vec![];
+        let code = {
+            let cpu = AwaCPU::with_io(&program, 0, io::empty(), &mut output);
+            let mut debugger = Debugger::new(cpu, script.as_bytes(), &mut messages);
+            debugger.run().unwrap();
+            debugger.exit_code()
+        };
+        let messages = String::from_utf8(messages).unwrap();
+        return (messages, String::from_utf8(output).unwrap(), code);
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(parse_command("s"), Ok(Command::Step(1)));
+        assert_eq!(parse_command("step 3"), Ok(Command::Step(3)));
+        assert_eq!(parse_command("b 4"), Ok(Command::Break(4)));
+        assert_eq!(parse_command("break lbl 2"), Ok(Command::BreakLabel(2)));
+        assert_eq!(parse_command("f"), Ok(Command::Finish));
+        assert_eq!(parse_command("l"), Ok(Command::List(5)));
+        assert!(parse_command("").is_err());
+        assert!(parse_command("s x").is_err());
+        assert!(parse_command("b lbl 300").is_err());
+        assert!(parse_command("jump").is_err());
+    }
+
+    #[test]
+    fn step() {
+        // An empty line repeats the previous command
+        let (messages, output, code) = debug_script("blo 1\nblo 2\nadd\npr1\n", "s\n\nq\n");
+        assert_eq!(
+            messages,
+            "Loaded 4 awatisms. Type 'help' for a list of commands.\n\
+             -> [0] blo 'W'\n\
+             (awa) [0] blo 'W'\n\
+             -> [1] blo 'a'\n\
+             (awa) [1] blo 'a'\n\
+             -> [2] 4dd\n\
+             (awa) "
+        );
+        assert_eq!((output.as_str(), code), ("", 0));
+    }
+
+    #[test]
+    fn breakpoints() {
+        let src = "blo 1\nlbl 0\nblo 2\nadd\npr1\n";
+        let (messages, output, code) =
+            debug_script(src, "b 3\nb lbl 0\nb lbl 5\ni\nc\nc\nabyss\nc\n");
+        assert!(messages.contains("(awa) Breakpoint at [3] 4dd\n"));
+        assert!(messages.contains("(awa) Breakpoint at [1] lbl 0\n"));
+        assert!(messages.contains("(awa) Label 5 does not exist\n"));
+        assert!(messages.contains("(awa) Breakpoint at [1] lbl 0\nBreakpoint at [3] 4dd\n"));
+        assert!(messages.contains("(awa) Breakpoint at [1]\n-> [1] lbl 0\n"));
+        assert!(messages.contains("(awa) Breakpoint at [3]\n-> [3] 4dd\n(awa) [2] 'a', 'W'\n"));
+        assert!(messages.ends_with("(awa) Program ended.\n(awa) "));
+        assert_eq!((output.as_str(), code), ("3 ", 0));
+    }
+
+    #[test]
+    fn finish() {
+        // finish ignores breakpoints
+        let (messages, output, code) = debug_script("blo 1\nblo 2\nadd\npr1\n", "b 2\nf\ns\n");
+        assert!(!messages.contains("-> [2]"));
+        assert!(
+            messages.ends_with("(awa) Program ended.\n(awa) The program is not running\n(awa) ")
+        );
+        assert_eq!((output.as_str(), code), ("3 ", 0));
+    }
+
+    #[test]
+    fn runtime_error() {
+        let (messages, _, code) = debug_script("blo 1\nprn\nprn\n", "c\n");
+        assert!(messages
+            .contains("Error executing instruction 2: Bubble Abyss accessed but it is empty"));
+        assert_eq!(code, 5);
+
+        // Quitting before the error leaves the exit code at 0
+        let (_, _, code) = debug_script("blo 1\nprn\nprn\n", "s\nq\nc\n");
+        assert_eq!(code, 0);
+    }
+}
@@ -1,11 +1,11 @@
-use std::fmt;
+use std::{fmt, time::Duration};
 
 #[derive(Debug, Clone)]
 pub enum AwawaLoadError {
-    AwawaParseError,
-    MissingInitialAwaError,
-    MalformedAwatismError,
-    UnknownAwatismError(u8),
+    AwawaParseError(AwatalkSpan),
+    MissingInitialAwaError(AwatalkSpan),
+    MalformedAwatismError(AwatalkSpan),
+    UnknownAwatismError(u8, AwatalkSpan),
 }
 
 #[derive(Debug, Clone)]
@@ -13,28 +13,106 @@ pub enum AwawaError {
     BubbleAbyssEmpty,
     BubbleAbyssOutOfBounds,
     InvalidAwasciiCodeError(i32),
-    InvalidAwasciiCharError(char),
+    NumberTooLargeError(String),
     ReadLineError,
+    WriteError,
     NotANumberError(String),
+    DivisionByZeroError,
+    OverflowError,
+    InvalidLabelError(u8),
+    FuelExhaustedError(u64),
+    TimeoutError(Duration),
+    BubbleAbyssTooLargeError(usize),
+    BubbleNestingTooDeepError(usize),
+    EndOfProgramError(),
+}
+
+/// What went wrong in Awassembler source
+#[derive(Debug, Clone)]
+pub enum AwassembleErrorKind {
+    InvalidAwasciiCharError(char),
     UnknownAwatismError(String),
     MissingArgumentError,
     InvalidArgumentError,
-    InvalidLabelError(u8),
-    EndOfProgramError(),
+    UndefinedSymbolError(String),
+    DuplicateSymbolError(String),
+    TooManyLabelsError,
+    UnterminatedMacroError(String),
+    UnexpectedEndmError,
+    NestedMacroError,
+    MacroArgumentCountError(String, usize),
+    MacroRecursionError(String),
+    IncludeError(String, String),
+    IncludeCycleError(String),
+    ReadError(String, String),
+}
+
+/// The offending awas of an `AwawaLoadError`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AwatalkSpan {
+    /// Index of the awatism that was being decoded
+    pub index: usize,
+    /// 0-based character offset of the offending Awatalk
+    pub offset: usize,
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based character column
+    pub column: usize,
+    /// Length of the offending Awatalk in characters
+    pub len: usize,
+}
+
+/// An error in Awassembler source, pointing at the offending text
+#[derive(Debug, Clone)]
+pub struct AwassembleError {
+    pub kind: AwassembleErrorKind,
+    pub file: String,
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based character column where the offending text starts
+    pub column: usize,
+    /// Length of the offending text in characters
+    pub len: usize,
+    /// The complete source line
+    pub snippet: String,
+    /// Where the offending line came from, innermost first, e.g. the macro call it was expanded
+    /// from
+    pub notes: Vec<String>,
 }
 
 pub type AwawaResult = Result<(), AwawaError>;
 pub type AwawaLoadResult = Result<(), AwawaLoadError>;
 
+impl AwawaLoadError {
+    /// Where in the Awatalk the error is
+    pub fn span(&self) -> &AwatalkSpan {
+        match self {
+            Self::AwawaParseError(span)
+            | Self::MissingInitialAwaError(span)
+            | Self::MalformedAwatismError(span)
+            | Self::UnknownAwatismError(_, span) => return span,
+        }
+    }
+
+    pub(crate) fn span_mut(&mut self) -> &mut AwatalkSpan {
+        match self {
+            Self::AwawaParseError(span)
+            | Self::MissingInitialAwaError(span)
+            | Self::MalformedAwatismError(span)
+            | Self::UnknownAwatismError(_, span) => return span,
+        }
+    }
+}
+
 impl fmt::Display for AwawaLoadError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::AwawaParseError => {
+            Self::AwawaParseError(_) => {
                 write!(f, "Only 'awa' and 'wa' are allowed")
             }
-            Self::MissingInitialAwaError => write!(f, "Missing initial 'awa'"),
-            Self::MalformedAwatismError => write!(f, "Awatism malformed: arguments missing"),
-            Self::UnknownAwatismError(awa) => write!(f, "Awatism {awa} not implemented"),
+            Self::MissingInitialAwaError(_) => write!(f, "Missing initial 'awa'"),
+            Self::MalformedAwatismError(_) => write!(f, "Awatism malformed: arguments missing"),
+            Self::UnknownAwatismError(awa, _) => write!(f, "Awatism {awa} not implemented"),
         }
     }
 }
@@ -44,17 +122,55 @@ impl fmt::Display for AwawaError {
             Self::BubbleAbyssEmpty => write!(f, "Bubble Abyss accessed but it is empty"),
             Self::BubbleAbyssOutOfBounds => write!(f, "Bubble Abyss accessed out of bounds"),
             Self::InvalidAwasciiCodeError(val) => write!(f, "Invalid AwaSCII code {val}"),
+            Self::NumberTooLargeError(s) => write!(f, "Number {s} is too large"),
+            Self::ReadLineError => write!(f, "Failed to read input"),
+            Self::WriteError => write!(f, "Failed to write output"),
+            Self::NotANumberError(s) => write!(f, "Text '{s}' cannot be converted to number"),
+            Self::DivisionByZeroError => write!(f, "Division by zero"),
+            Self::OverflowError => write!(f, "Arithmetic overflow"),
+            Self::InvalidLabelError(l) => write!(f, "Label {l} is invalid"),
+            Self::FuelExhaustedError(max) => write!(f, "Executed the maximum of {max} awatisms"),
+            Self::TimeoutError(t) => write!(f, "Program ran longer than {t:?}"),
+            Self::BubbleAbyssTooLargeError(max) => {
+                write!(f, "Bubble Abyss grew larger than {max} bubbles")
+            }
+            Self::BubbleNestingTooDeepError(max) => {
+                write!(f, "Double Bubbles nested deeper than {max} levels")
+            }
+            Self::EndOfProgramError() => write!(f, "Program ended"),
+        }
+    }
+}
+impl fmt::Display for AwassembleErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
             Self::InvalidAwasciiCharError(val) => {
                 write!(f, "Character {val} cannot be represented in AwaSCII")
             }
-            Self::ReadLineError => write!(f, "Failed to read input"),
-            Self::NotANumberError(s) => write!(f, "Text '{s}' cannot be converted to number"),
             Self::UnknownAwatismError(s) => write!(f, "Unknown awatism '{s}'"),
             Self::MissingArgumentError => write!(f, "Awatism requires one or more arguments"),
             Self::InvalidArgumentError => write!(f, "The argument is invalid"),
-
-            Self::InvalidLabelError(l) => write!(f, "Label {l} is invalid"),
-            Self::EndOfProgramError() => write!(f, "Program ended"),
+            Self::UndefinedSymbolError(s) => write!(f, "'{s}' is not defined"),
+            Self::DuplicateSymbolError(s) => write!(f, "'{s}' is already defined"),
+            Self::TooManyLabelsError => write!(f, "Program needs more than 32 labels"),
+            Self::UnterminatedMacroError(s) => write!(f, "Macro '{s}' is missing .endm"),
+            Self::UnexpectedEndmError => write!(f, ".endm without .macro"),
+            Self::NestedMacroError => write!(f, "Macros cannot be defined inside a macro"),
+            Self::MacroArgumentCountError(s, 1) => write!(f, "Macro '{s}' expects 1 argument"),
+            Self::MacroArgumentCountError(s, n) => write!(f, "Macro '{s}' expects {n} arguments"),
+            Self::MacroRecursionError(s) => write!(f, "Macro '{s}' is nested too deeply"),
+            Self::IncludeError(s, e) => write!(f, "Cannot include '{s}': {e}"),
+            Self::IncludeCycleError(s) => write!(f, "'{s}' is already being included"),
+            Self::ReadError(s, e) => write!(f, "Cannot read '{s}': {e}"),
         }
     }
 }
+impl fmt::Display for AwassembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{0}:{1}:{2}: {3}",
+            self.file, self.line, self.column, self.kind
+        )
+    }
+}
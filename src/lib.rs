@@ -0,0 +1,30 @@
+//! Awatistic AWA5.0 interpreter library
+//!
+//! Provides everything needed to load, run, awassemble and disawassemble AWA5.0 programs.
+//! The `awatistic` binary is a thin CLI on top of this crate.
+#![allow(clippy::needless_return)]
+
+pub mod awacpu;
+pub mod awassembler;
+pub mod check;
+pub mod errors;
+pub mod program;
+pub mod validate;
+
+pub use awacpu::{
+    arithmetic::Arithmetic,
+    awatalk::disawassemble,
+    bubblestack::{BubbleItem, BubbleStack, Bubbles},
+    limits::Limits,
+    profile::{Outcomes, Profile, Region},
+    trace::JsonTrace,
+    AwaCPU, Awatism, StepEvent,
+};
+pub use awassembler::{awassemble, awassemble_named, print_awatism, print_awatisms};
+pub use check::{check, StackIssue};
+pub use errors::{
+    AwassembleError, AwassembleErrorKind, AwatalkSpan, AwawaError, AwawaLoadError, AwawaLoadResult,
+    AwawaResult,
+};
+pub use program::{Program, SourceLocation, SourceMap};
+pub use validate::{validate, Category, Finding, Level, Levels};
@@ -0,0 +1,101 @@
+use std::io::{self, Write};
+
+use awatistic::{print_awatism, Awatism, Program};
+
+/// Source line of the awatism at `ip`, prefixed by its file if it was included from another one
+fn source_line(program: &Program, ip: usize) -> String {
+    let source = match program.source() {
+        None => return String::from("-"),
+        Some(source) => source,
+    };
+    return match (source.file(ip), source.line(ip)) {
+        (Some(file), Some(line)) if source.files.first().is_some_and(|x| x != file) => {
+            format!("{file}:{line}")
+        }
+        (_, Some(line)) => line.to_string(),
+        _ => String::from("-"),
+    };
+}
+
+/// The operand bits of an awatism, 8 for `blo` and 5 for the others
+fn operand_bits(awatism: Awatism) -> String {
+    return match (awatism, awatism.operand()) {
+        (Awatism::Blo(val), _) => format!("{0:08b}", val as u8),
+        (_, Some(operand)) => format!("{operand:05b}"),
+        (_, None) => String::new(),
+    };
+}
+
+/// Prints a table with the source line, instruction index, opcode and operand bits and Awatalk of
+/// every awatism. The Awatalk of all rows after `awa` is the Awatalk of the program.
+pub fn write_listing<W: Write>(out: &mut W, program: &Program) -> io::Result<()> {
+    let mut rows = vec![[
+        String::from("line"),
+        String::from("ip"),
+        String::from("awatism"),
+        String::from("opcode"),
+        String::from("operand"),
+        String::from("awatalk"),
+    ]];
+    for (ip, awatism) in program.awatisms().iter().enumerate() {
+        let mut awatalk = String::new();
+        print_awatism(*awatism, &mut awatalk).map_err(io::Error::other)?;
+        rows.push([
+            source_line(program, ip),
+            ip.to_string(),
+            awatism.to_string(),
+            format!("{0:05b}", awatism.discriminant()),
+            operand_bits(*awatism),
+            // Quoted because fragments can start with a space
+            format!("\"{awatalk}\""),
+        ]);
+    }
+
+    let mut widths = [0; 6];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = usize::max(*width, cell.chars().count());
+        }
+    }
+    for [line, ip, awatism, opcode, operand, awatalk] in &rows {
+        writeln!(
+            out,
+            "{line:>0$}  {ip:>1$}  {awatism:2$}  {opcode:3$}  {operand:4$}  {awatalk}",
+            widths[0], widths[1], widths[2], widths[3], widths[4]
+        )?;
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, io::BufReader};
+
+    use awatistic::Program;
+
+    use super::write_listing;
+
+    #[test]
+    fn listing() {
+        let file = "tests/include/listing.awasm";
+        let src = BufReader::new(File::open(file).unwrap());
+        let program = Program::from_awasm(file, src).unwrap();
+        let mut out = vec![];
+        write_listing(&mut out, &program).unwrap();
+        // Rows expanded from the macro show the file they were included from
+        let expected = [
+            r#"                           line  ip  awatism  opcode  operand   awatalk"#,
+            r#"                              3   0  blo 'W'  00101   00000001  " awa awawa awawa awa awa awa awa awa awa awawa""#,
+            r#"                              4   1  pr1      00010             " awa awa awawa awa""#,
+            r#"tests/include/lib/print.awasm:3   2  blo 'w'  00101   00000011  " awa awawa awawa awa awa awa awa awa awawawa""#,
+            r#"tests/include/lib/print.awasm:4   3  blo 'a'  00101   00000010  " awa awawa awawa awa awa awa awa awa awawa awa""#,
+            r#"tests/include/lib/print.awasm:5   4  srn 2    01001   00010     " awawa awa awawa awa awa awawa awa""#,
+            r#"tests/include/lib/print.awasm:6   5  prn      00001             " awa awa awa awawa""#,
+            r#"                              6   6  trm      11111             "wawawawawa""#,
+        ];
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            expected.map(|l| return format!("{l}\n")).concat()
+        );
+    }
+}
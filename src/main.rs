@@ -1,19 +1,23 @@
-mod awacpu;
-mod errors;
+#![allow(clippy::needless_return)]
 
-use awassembler::{awassemble, print_awatisms};
-use clap::{Parser, Subcommand};
-use errors::AwawaError;
+mod coverage;
+mod debugger;
+mod listing;
+mod profiler;
+
+use awatistic::{
+    print_awatisms, Arithmetic, AwaCPU, AwassembleError, AwawaError, AwawaLoadError, Category,
+    Level, Levels, Limits, Program, SourceMap,
+};
+use clap::{Args, Parser, Subcommand};
 use std::{
     fs::{self, File},
     io::{self, BufRead, BufReader},
-    path::PathBuf,
+    path::{Path, PathBuf},
     process::ExitCode,
+    time::Duration,
 };
 
-use crate::awacpu::AwaCPU;
-mod awassembler;
-
 #[derive(Parser)]
 #[command(author, version, about)]
 /// Awatistic AWA5.0 Interpreter
@@ -26,14 +30,30 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Run Awatalk
+    /// Run Awatalk or awasm
     Run {
-        /// File containing Awatalk to run (defaults to stdin)
+        /// File containing Awatalk or awasm to run (defaults to stdin)
+        #[arg(short, long, value_name = "FILE")]
+        file: Option<PathBuf>,
+        #[command(flatten)]
+        args: RunArgs,
+    },
+    /// Debug Awatalk interactively
+    Debug {
+        /// File containing Awatalk to debug
+        #[arg(short, long, value_name = "FILE")]
+        file: PathBuf,
+        /// Integer arithmetic: checked, wrapping, saturating or bignum
+        #[arg(long, value_name = "MODE", default_value_t = Arithmetic::Checked)]
+        arith: Arithmetic,
+    },
+    /// Check that no awatism can run out of bubbles in the Bubble Abyss
+    Check {
+        /// File containing Awatalk to check (defaults to stdin)
         #[arg(short, long, value_name = "FILE")]
         file: Option<PathBuf>,
-        /// Verbosity of output, can be specified up to three times
-        #[arg(short, long, action = clap::ArgAction::Count)]
-        verbose: u8,
+        #[command(flatten)]
+        levels: LevelArgs,
     },
     /// Disawassemble Awatalk
     Disawassemble {
@@ -46,28 +66,163 @@ enum Commands {
         /// File containing Awatisms to awassemble (defaults to stdin)
         #[arg(short, long, value_name = "FILE")]
         file: Option<PathBuf>,
+        /// Print a table of every awatism with its source line, bits and Awatalk instead of the
+        /// Awatalk
+        #[arg(long)]
+        listing: bool,
+        /// Write the source location of every awatism and the label names to FILE, for use with
+        /// run --debug-info
+        #[arg(long, value_name = "FILE")]
+        debug_info: Option<PathBuf>,
     },
 }
 
+#[derive(Args)]
+struct RunArgs {
+    /// Verbosity of output, can be specified up to three times
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Awassemble the input before running it. Without this flag files ending in .awasm and
+    /// input starting with an awatism or directive are awassembled.
+    #[arg(long)]
+    awasm: bool,
+    /// Integer arithmetic: checked, wrapping, saturating or bignum
+    #[arg(long, value_name = "MODE", default_value_t = Arithmetic::Checked)]
+    arith: Arithmetic,
+    #[command(flatten)]
+    limits: LimitArgs,
+    /// Write a JSON line describing every executed awatism to FILE
+    #[arg(long, value_name = "FILE")]
+    trace_json: Option<PathBuf>,
+    /// Read the debug info written by awassemble --debug-info from FILE to show awasm lines and
+    /// label names
+    #[arg(long, value_name = "FILE")]
+    debug_info: Option<PathBuf>,
+    #[command(flatten)]
+    profile: ProfileArgs,
+    #[command(flatten)]
+    levels: LevelArgs,
+}
+
+#[derive(Args)]
+struct LimitArgs {
+    /// Stop after executing N awatisms
+    #[arg(long, value_name = "N")]
+    max_steps: Option<u64>,
+    /// Stop after running for SECONDS
+    #[arg(long, value_name = "SECONDS", value_parser = parse_timeout)]
+    timeout: Option<Duration>,
+    /// Stop when the Bubble Abyss holds more than N bubbles
+    #[arg(long, value_name = "N")]
+    max_abyss: Option<usize>,
+    /// Stop when Double Bubbles are nested more than N levels deep
+    #[arg(long, value_name = "N")]
+    max_depth: Option<usize>,
+}
+
+#[derive(Args)]
+struct ProfileArgs {
+    /// Print how often each awatism and label was executed to stderr
+    #[arg(long)]
+    profile: bool,
+    /// Write the execution counts to FILE in the folded stack format used by flamegraph tools
+    #[arg(long, value_name = "FILE")]
+    profile_folded: Option<PathBuf>,
+    /// Write an LCOV coverage report to FILE and print a coverage summary to stderr
+    #[arg(long, value_name = "FILE")]
+    coverage: Option<PathBuf>,
+}
+
+/// Levels of the load-time checks: undefined-label, duplicate-label, unreachable and
+/// unused-label. Deny wins over warn, warn over allow.
+#[derive(Args)]
+struct LevelArgs {
+    /// Do not report CATEGORY
+    #[arg(long, value_name = "CATEGORY")]
+    allow: Vec<Category>,
+    /// Report CATEGORY as a warning
+    #[arg(long, value_name = "CATEGORY")]
+    warn: Vec<Category>,
+    /// Report CATEGORY as an error and do not run the program
+    #[arg(long, value_name = "CATEGORY")]
+    deny: Vec<Category>,
+}
+
+impl LevelArgs {
+    fn to_levels(&self) -> Levels {
+        let mut levels = Levels::default();
+        for (categories, level) in [
+            (&self.allow, Level::Allow),
+            (&self.warn, Level::Warn),
+            (&self.deny, Level::Deny),
+        ] {
+            for category in categories {
+                levels.set(*category, level);
+            }
+        }
+        return levels;
+    }
+}
+
+impl LimitArgs {
+    fn to_limits(&self) -> Limits {
+        return Limits {
+            max_steps: self.max_steps,
+            timeout: self.timeout,
+            max_abyss_size: self.max_abyss,
+            max_depth: self.max_depth,
+        };
+    }
+}
+
+fn parse_timeout(s: &str) -> Result<Duration, String> {
+    let secs: f64 = s.parse().map_err(|e| format!("{e}"))?;
+    if !secs.is_finite() || secs < 0.0 {
+        return Err(String::from(
+            "must be a finite, non-negative number of seconds",
+        ));
+    }
+    return Duration::try_from_secs_f64(secs).map_err(|_| String::from("too large"));
+}
+
 fn main() -> ExitCode {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Run { file, verbose } => match file_or_stdin_to_string(file) {
+        Commands::Run { file, args } => match file_or_stdin_to_string(&file) {
+            Err(e) => {
+                eprintln!("Error reading awawa input: {e}");
+                ExitCode::from(3)
+            }
+            Ok(awa) => run(&input_name(&file), awa.as_str(), args),
+        },
+        Commands::Debug { file, arith } => match fs::read_to_string(&file) {
+            Err(e) => {
+                eprintln!("Error reading awawa input: {e}");
+                ExitCode::from(3)
+            }
+            Ok(awa) => debugger::debug(&file.display().to_string(), awa.as_str(), arith),
+        },
+        Commands::Check { file, levels } => match file_or_stdin_to_string(&file) {
             Err(e) => {
                 eprintln!("Error reading awawa input: {e}");
                 ExitCode::from(3)
             }
-            Ok(awa) => run(awa.as_str(), verbose),
+            Ok(awa) => check(&input_name(&file), awa.as_str(), levels.to_levels()),
         },
-        Commands::Disawassemble { file } => match file_or_stdin_to_string(file) {
+        Commands::Disawassemble { file } => match file_or_stdin_to_string(&file) {
             Err(e) => {
                 eprintln!("Error reading awawa input: {e}");
                 ExitCode::from(6)
             }
-            Ok(awa) => disawassemble(awa.as_str()),
+            Ok(awa) => disawassemble(&input_name(&file), awa.as_str()),
         },
-        Commands::Awassemble { file } => {
+        Commands::Awassemble {
+            file,
+            listing,
+            debug_info,
+        } => {
+            let name = input_name(&file);
             let reader = match file_or_stdin(file) {
                 Err(e) => {
                     eprintln!("Error reading awawa input: {e}");
@@ -76,25 +231,34 @@ fn main() -> ExitCode {
                 Ok(r) => r,
             };
 
-            let mut awatisms = vec![];
-            match awassemble(reader, &mut awatisms) {
+            let program = match Program::from_awasm(&name, reader) {
+                Ok(program) => program,
                 Err(e) => {
-                    for (i, awa) in awatisms.iter().enumerate() {
-                        eprintln!("[{i}] {awa}");
-                    }
-                    eprintln!("Error awassembling instruction: {e}");
+                    print_awassemble_error(&e);
                     return ExitCode::from(1);
                 }
-                _ => (),
+            };
+
+            if let (Some(debug_info), Some(source)) = (debug_info, program.source()) {
+                let res = File::create(&debug_info).and_then(|mut f| source.write(&mut f));
+                if let Err(e) = res {
+                    eprintln!("Error writing {0}: {e}", debug_info.display());
+                    return ExitCode::from(3);
+                }
             }
 
-            let mut out = String::new();
-            match print_awatisms(awatisms, &mut out) {
-                Err(e) => {
-                    eprintln!("Error printing awatisms: {e}");
+            if listing {
+                if let Err(e) = listing::write_listing(&mut io::stdout(), &program) {
+                    eprintln!("Error printing listing: {e}");
                     return ExitCode::from(2);
                 }
-                _ => (),
+                return ExitCode::from(0);
+            }
+
+            let mut out = String::new();
+            if let Err(e) = print_awatisms(program.awatisms().iter().copied(), &mut out) {
+                eprintln!("Error printing awatisms: {e}");
+                return ExitCode::from(2);
             }
             println!("{out}");
             ExitCode::from(0)
@@ -102,7 +266,15 @@ fn main() -> ExitCode {
     }
 }
 
-fn file_or_stdin_to_string(file: Option<PathBuf>) -> Result<String, std::io::Error> {
+/// Name of the input for diagnostics
+fn input_name(file: &Option<PathBuf>) -> String {
+    match file {
+        Some(file) if file.as_os_str() != "-" => return file.display().to_string(),
+        _ => return String::from("<stdin>"),
+    }
+}
+
+fn file_or_stdin_to_string(file: &Option<PathBuf>) -> Result<String, std::io::Error> {
     match file {
         Some(file) => {
             if file.as_os_str() == "-" {
@@ -121,10 +293,7 @@ fn file_or_stdin(file: Option<PathBuf>) -> Result<Box<dyn BufRead>, std::io::Err
             if file.as_os_str() == "-" {
                 Box::new(BufReader::new(io::stdin()))
             } else {
-                match File::open(file) {
-                    Ok(f) => Box::new(BufReader::new(f)),
-                    Err(e) => return Err(e),
-                }
+                Box::new(BufReader::new(File::open(file)?))
             }
         }
         None => Box::new(BufReader::new(io::stdin())),
@@ -133,34 +302,327 @@ fn file_or_stdin(file: Option<PathBuf>) -> Result<Box<dyn BufRead>, std::io::Err
     return Ok(reader);
 }
 
-fn disawassemble(awa: &str) -> ExitCode {
-    let mut cpu = AwaCPU::new(awa.chars(), 0);
-    match cpu.disawassemble() {
+/// Prints a diagnostic for an awassemble error with the macro calls and includes it came from
+fn print_awassemble_error(e: &AwassembleError) {
+    let location = format!("{0}:{1}:{2}", e.file, e.line, e.column);
+    print_diagnostic(
+        &e.kind.to_string(),
+        &location,
+        e.line,
+        &e.snippet,
+        e.column,
+        e.len,
+        &e.notes,
+    );
+}
+
+/// Prints a rustc style diagnostic underlining `len` characters of `snippet` starting at the
+/// 1-based `column`. `line` is shown in the gutter, `notes` below the snippet.
+fn print_diagnostic(
+    message: &str,
+    location: &str,
+    line: usize,
+    snippet: &str,
+    column: usize,
+    len: usize,
+    notes: &[String],
+) {
+    eprintln!("error: {message}");
+    print_snippet(location, line, snippet, column, len, notes);
+}
+
+/// Prints the snippet of a diagnostic without its message
+fn print_snippet(
+    location: &str,
+    line: usize,
+    snippet: &str,
+    column: usize,
+    len: usize,
+    notes: &[String],
+) {
+    let gutter = " ".repeat(line.to_string().len());
+    // Keep tabs so the carets line up with the snippet
+    let indent: String = snippet
+        .chars()
+        .take(column.saturating_sub(1))
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+
+    eprintln!("{gutter}--> {location}");
+    eprintln!("{gutter} |");
+    eprintln!("{line} | {snippet}");
+    eprintln!("{gutter} | {indent}{0}", "^".repeat(len.max(1)));
+    for note in notes {
+        eprintln!("{gutter} = note: {note}");
+    }
+}
+
+/// Prints a diagnostic for a load error, showing the Awatalk around the offending awas
+pub fn print_load_error(name: &str, awa: &str, e: &AwawaLoadError) {
+    const CONTEXT: usize = 40;
+
+    let span = e.span();
+    let line: Vec<char> = awa
+        .lines()
+        .nth(span.line - 1)
+        .unwrap_or_default()
+        .chars()
+        .collect();
+    let column = span.column - 1;
+    let end = usize::min(line.len(), column + span.len + CONTEXT);
+    let start = usize::min(column.saturating_sub(CONTEXT), end);
+
+    let mut snippet: String = line[start..end].iter().collect();
+    let mut snippet_column = column - start + 1;
+    if start > 0 {
+        snippet = format!("...{snippet}");
+        snippet_column += 3;
+    }
+    if end < line.len() {
+        snippet.push_str("...");
+    }
+
+    let message = format!("{e} (in awatism {0})", span.index);
+    let location = format!("{name}:{0}:{1}", span.line, span.column);
+    let len = usize::min(span.len, end.saturating_sub(column));
+    print_diagnostic(
+        &message,
+        &location,
+        span.line,
+        &snippet,
+        snippet_column,
+        len,
+        &[],
+    );
+}
+
+fn disawassemble(name: &str, awa: &str) -> ExitCode {
+    let mut awatisms = vec![];
+    let res = awatistic::disawassemble(awa.chars(), &mut awatisms);
+    for awatism in awatisms {
+        println!("{awatism}");
+    }
+    match res {
         Err(e) => {
-            eprintln!("Error disawassembling instruction: {e}");
+            print_load_error(name, awa, &e);
             return ExitCode::from(7);
         }
         _ => return ExitCode::from(0),
     }
 }
 
-fn run(awa: &str, verbose: u8) -> ExitCode {
-    let mut cpu = AwaCPU::new(awa.chars(), verbose);
-    match cpu.load_program() {
+/// Prints the awasm line the awatism at `ip` came from if the program has a source map and the
+/// source file can be read. `src` is the input called `name`, which may not be a file.
+fn print_source(name: &str, src: &str, program: &Program, ip: usize) {
+    let source = match program.source() {
+        None => return,
+        Some(source) => source,
+    };
+    let (file, location) = match (source.file(ip), source.locations.get(ip)) {
+        (Some(file), Some(location)) => (file, location),
+        _ => return,
+    };
+    let text = if file == name {
+        Some(String::from(src))
+    } else {
+        fs::read_to_string(file).ok()
+    };
+    let text = text.and_then(|text| text.lines().nth(location.line - 1).map(String::from));
+    if let Some(text) = text {
+        let len = text
+            .chars()
+            .skip(location.column - 1)
+            .take_while(|c| !c.is_whitespace())
+            .count();
+        let at = format!("{file}:{0}:{1}", location.line, location.column);
+        print_snippet(&at, location.line, &text, location.column, len, &[]);
+    }
+}
+
+/// Location of the awatism at `ip` for diagnostics, its awasm line if the program has a source map
+fn location(name: &str, program: &Program, ip: usize) -> String {
+    return match program
+        .source()
+        .and_then(|s| Some((s.file(ip)?, s.line(ip)?)))
+    {
+        Some((file, line)) => format!("{file}:{line}"),
+        None => String::from(name),
+    };
+}
+
+/// Prints the findings of the load-time checks that are not allowed by `levels`.
+/// Returns whether any of them is denied.
+fn print_findings(name: &str, program: &Program, levels: Levels) -> bool {
+    let mut denied = false;
+    for finding in awatistic::validate(program) {
+        let category = finding.category();
+        let severity = match levels.get(category) {
+            Level::Allow => continue,
+            Level::Warn => "warning",
+            Level::Deny => "error",
+        };
+        denied |= levels.get(category) == Level::Deny;
+        let location = location(name, program, finding.ip());
+        eprintln!("{severity}: {location}: {finding} [{category}]");
+    }
+    return denied;
+}
+
+fn check(name: &str, awa: &str, levels: Levels) -> ExitCode {
+    let program = match Program::from_awatalk(awa) {
         Err(e) => {
+            print_load_error(name, awa, &e);
+            return ExitCode::from(4);
+        }
+        Ok(program) => program,
+    };
+
+    let mut denied = print_findings(name, &program, levels);
+    let issues = awatistic::check(&program);
+    for issue in &issues {
+        denied |= issue.always_fails();
+        let severity = if issue.always_fails() {
+            "error"
+        } else {
+            "warning"
+        };
+        let location = location(name, &program, issue.ip);
+        eprintln!("{severity}: {location}: {issue}");
+    }
+    if denied {
+        return ExitCode::from(12);
+    }
+    return ExitCode::from(0);
+}
+
+/// Awatisms as written in awasm
+const MNEMONICS: [&str; 22] = [
+    "nop", "prn", "pr1", "red", "r3d", "blo", "sbm", "pop", "dpl", "srn", "mrg", "add", "sub",
+    "mul", "div", "cnt", "lbl", "jmp", "eql", "lss", "gr8", "trm",
+];
+
+/// Returns true if `src` is awasm rather than Awatalk, judging by the extension of `name` or, if
+/// that does not tell, by whether the first word of the input is an awatism or a directive.
+/// Anything else is left to Awatalk, which ignores characters other than awas.
+fn is_awasm(name: &str, src: &str) -> bool {
+    match Path::new(name).extension().and_then(|x| x.to_str()) {
+        Some("awasm") => return true,
+        Some("awa") => return false,
+        _ => (),
+    }
+    let word = src
+        .lines()
+        .map(|x| return x.split('#').next().unwrap_or_default().trim())
+        .find(|x| !x.is_empty())
+        .and_then(|x| x.split_whitespace().next());
+    return match word {
+        None => false,
+        Some(word) => word.starts_with('.') || MNEMONICS.contains(&word.to_lowercase().as_str()),
+    };
+}
+
+/// Decodes the Awatalk to run, printing what could be decoded before an error with `verbose`
+fn load_awatalk(name: &str, awa: &str, verbose: u8) -> Result<Program, ExitCode> {
+    let mut awatisms = vec![];
+    if let Err(e) = awatistic::disawassemble(awa.chars(), &mut awatisms) {
+        if verbose > 0 {
             eprintln!("Failed to load program:");
-            for (i, awatism) in cpu.get_program().iter().enumerate() {
+            for (i, awatism) in awatisms.iter().enumerate() {
                 eprintln!("[{i}] {awatism}");
             }
-            eprintln!(
-                "Error parsing instruction {0}: {e}",
-                cpu.get_program().len()
-            );
+        }
+        print_load_error(name, awa, &e);
+        return Err(ExitCode::from(4));
+    }
+    return Ok(Program::from_awatisms(awatisms));
+}
+
+/// Loads `src` as awasm if `awasm` forces it or `is_awasm` detects it, otherwise as Awatalk
+fn load_program(name: &str, src: &str, awasm: bool, verbose: u8) -> Result<Program, ExitCode> {
+    if awasm || is_awasm(name, src) {
+        return Program::from_awasm(name, src.as_bytes()).map_err(|e| {
+            print_awassemble_error(&e);
             return ExitCode::from(4);
+        });
+    }
+    return load_awatalk(name, src, verbose);
+}
+
+fn run(name: &str, src: &str, args: RunArgs) -> ExitCode {
+    let verbose = args.verbose;
+    let mut program = match load_program(name, src, args.awasm, verbose) {
+        Err(code) => return code,
+        Ok(program) => program,
+    };
+    if verbose >= 3 {
+        for (i, awatism) in program.awatisms().iter().enumerate() {
+            println!("Load: [{i}] {awatism}");
+        }
+    }
+
+    if let Some(debug_info) = &args.debug_info {
+        let source = File::open(debug_info).and_then(|f| SourceMap::read(BufReader::new(f)));
+        match source {
+            Err(e) => {
+                eprintln!("Error reading {0}: {e}", debug_info.display());
+                return ExitCode::from(3);
+            }
+            Ok(source) if source.locations.len() != program.len() => {
+                eprintln!(
+                    "Debug info {0} describes {1} awatisms, but the program has {2}",
+                    debug_info.display(),
+                    source.locations.len(),
+                    program.len()
+                );
+                return ExitCode::from(4);
+            }
+            Ok(source) => program = program.with_source(source),
+        }
+    }
+    if print_findings(name, &program, args.levels.to_levels()) {
+        return ExitCode::from(4);
+    }
+
+    let mut cpu = AwaCPU::new(&program, verbose);
+    cpu.set_arithmetic(args.arith);
+    cpu.set_limits(args.limits.to_limits());
+    if let Some(trace_json) = args.trace_json {
+        match File::create(&trace_json) {
+            Err(e) => {
+                eprintln!("Error creating {0}: {e}", trace_json.display());
+                return ExitCode::from(3);
+            }
+            Ok(f) => cpu.set_trace(f),
+        }
+    }
+    let profile = args.profile;
+    if profile.profile || profile.profile_folded.is_some() || profile.coverage.is_some() {
+        cpu.enable_profile();
+    }
+
+    let res = cpu.run();
+    if let Some(counts) = cpu.get_profile() {
+        if profile.profile {
+            let _ = profiler::print_report(&mut io::stderr(), &program, counts);
+        }
+        if let Some(folded) = &profile.profile_folded {
+            let res = File::create(folded)
+                .and_then(|mut f| profiler::write_folded(&mut f, name, &program, counts));
+            if let Err(e) = res {
+                eprintln!("Error writing {0}: {e}", folded.display());
+            }
+        }
+        if let Some(lcov) = &profile.coverage {
+            let _ = coverage::print_summary(&mut io::stderr(), name, &program, counts);
+            let res = File::create(lcov)
+                .and_then(|mut f| coverage::write_lcov(&mut f, name, &program, counts));
+            if let Err(e) = res {
+                eprintln!("Error writing {0}: {e}", lcov.display());
+            }
         }
-        _ => (),
     }
-    match cpu.run() {
+
+    match res {
         Err(AwawaError::EndOfProgramError()) => {
             if verbose > 0 {
                 println!("Program ended.");
@@ -169,19 +631,121 @@ fn run(awa: &str, verbose: u8) -> ExitCode {
         }
         Err(e) => {
             eprintln!("Error executing instruction {0}: {e}", cpu.get_ip());
+            print_source(name, src, &program, cpu.get_ip());
             eprintln!("Bubble Abyss:");
             eprintln!("{0}", cpu.get_bubble_abyss());
             eprintln!("Program:");
-            for (i, awatism) in cpu.get_program().iter().enumerate() {
+            for i in 0..program.len() {
                 if i == cpu.get_ip() {
                     eprint!("->");
                 } else {
                     eprint!("  ");
                 }
-                eprintln!("[{i}] {awatism}");
+                eprintln!("[{i}] {0}", program.describe(i));
             }
-            return ExitCode::from(5);
+            return ExitCode::from(runtime_exit_code(&e));
         }
         _ => return ExitCode::from(0),
     }
 }
+
+/// Exit code of `run` for a runtime error, resource limits get their own codes
+fn runtime_exit_code(e: &AwawaError) -> u8 {
+    return match e {
+        AwawaError::FuelExhaustedError(_) => 8,
+        AwawaError::TimeoutError(_) => 9,
+        AwawaError::BubbleAbyssTooLargeError(_) => 10,
+        AwawaError::BubbleNestingTooDeepError(_) => 11,
+        _ => 5,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, time::Duration};
+
+    use awatistic::{AwaCPU, Awatism, AwawaError, Level, Levels};
+    use clap::Parser;
+
+    use super::{is_awasm, load_program, location, parse_timeout, Cli, Commands};
+
+    #[test]
+    fn awasm_detection() {
+        // The extension wins over the content
+        assert!(is_awasm("loop.awasm", "awa awa"));
+        assert!(!is_awasm("loop.awa", "blo 1"));
+
+        // Otherwise the first word that is not a comment decides
+        assert!(is_awasm("<stdin>", "blo 1\npr1\n"));
+        assert!(is_awasm(
+            "prog.txt",
+            "\n  \n# awa awa\n   # comment\n  BLO 1 # awa\n"
+        ));
+        assert!(is_awasm("<stdin>", "# prints aw\n.include \"lib.awasm\"\n"));
+        assert!(is_awasm("<stdin>", "trm# end"));
+        assert!(!is_awasm("<stdin>", "awa awawa awa wa\n"));
+        assert!(!is_awasm("<stdin>", "\n\nAWA wa awa\n"));
+        assert!(!is_awasm("<stdin>", "awawa"));
+        assert!(!is_awasm("<stdin>", "await 1"));
+
+        // Awatalk with punctuation between the awas is still Awatalk
+        let awa = "awa, awa awawa awa awa awa awawa";
+        assert!(!is_awasm("<stdin>", awa));
+        let program = load_program("<stdin>", awa, false, 0).unwrap();
+        assert_eq!(program.awatisms(), [Awatism::R3d]);
+        assert!(!is_awasm("<stdin>", ""));
+
+        // --awasm forces awasm whatever the input looks like
+        let program = load_program("prog.awa", "blo 1", true, 0).unwrap();
+        assert_eq!(program.len(), 1);
+        assert!(load_program("prog.awa", "blo 1", false, 0).is_err());
+        assert!(load_program("prog", "awa", true, 0).is_err());
+        assert!(load_program("prog", "awa", false, 0).is_ok());
+    }
+
+    #[test]
+    fn awasm_runtime_error_location() {
+        let src = "# pops more than it pushes\nblo 1\n\n  pop\n  pop\n";
+        let program = load_program("pop.awasm", src, false, 0).unwrap();
+        let mut cpu = AwaCPU::with_io(&program, 0, io::empty(), io::sink());
+        assert!(matches!(cpu.run(), Err(AwawaError::BubbleAbyssEmpty)));
+        assert_eq!(location("pop.awasm", &program, cpu.get_ip()), "pop.awasm:5");
+        let source = program.source().unwrap();
+        assert_eq!(source.locations[cpu.get_ip()].column, 3);
+    }
+
+    /// Parses the levels given to `run`
+    fn run_levels(flags: &[&str]) -> Levels {
+        let args = [&["awatistic", "run"], flags].concat();
+        return match Cli::parse_from(args).command {
+            Commands::Run { args, .. } => args.levels.to_levels(),
+            _ => unreachable!(),
+        };
+    }
+
+    #[test]
+    fn levels() {
+        // Every category warns unless a flag changes it
+        assert_eq!(run_levels(&[]), Levels::default());
+        let levels = run_levels(&["--allow", "unused-label", "--deny", "undefined-label"]);
+        assert_eq!(levels.unused_label, Level::Allow);
+        assert_eq!(levels.undefined_label, Level::Deny);
+        assert_eq!(levels.duplicate_label, Level::Warn);
+        assert_eq!(levels.unreachable, Level::Warn);
+
+        // Deny wins over warn, warn over allow
+        let levels = run_levels(&["--deny", "unreachable", "--warn", "unreachable"]);
+        assert_eq!(levels.unreachable, Level::Deny);
+        let levels = run_levels(&["--allow", "unreachable", "--warn", "unreachable"]);
+        assert_eq!(levels.unreachable, Level::Warn);
+    }
+
+    #[test]
+    fn timeout() {
+        assert_eq!(parse_timeout("1.5"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_timeout("0"), Ok(Duration::ZERO));
+        for s in ["1e30", "inf", "NaN", "-1", "abc"] {
+            assert!(parse_timeout(s).is_err(), "{s}");
+        }
+    }
+}
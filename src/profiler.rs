@@ -0,0 +1,171 @@
+use std::io::{self, Write};
+
+use awatistic::{Profile, Program, Region};
+
+/// Prints the awatisms and labels sorted by how often they were executed
+pub fn print_report<W: Write>(out: &mut W, program: &Program, profile: &Profile) -> io::Result<()> {
+    let total = profile.total();
+    let percent = |count: u64| -> f64 {
+        if total == 0 {
+            return 0.0;
+        }
+        return count as f64 * 100.0 / total as f64;
+    };
+
+    writeln!(out, "Awatisms executed: {total}")?;
+    writeln!(out, "Max Bubble Abyss size: {0}", profile.max_abyss_size())?;
+    writeln!(
+        out,
+        "Double Bubble allocations: {0}",
+        profile.double_bubble_allocations()
+    )?;
+    writeln!(
+        out,
+        "Bubble Abyss allocations: {0}",
+        profile.abyss_allocations()
+    )?;
+
+    let mut hot: Vec<_> = profile
+        .counts()
+        .iter()
+        .enumerate()
+        .filter(|(_, count)| **count > 0)
+        .collect();
+    hot.sort_by(|(ia, ca), (ib, cb)| cb.cmp(ca).then(ia.cmp(ib)));
+
+    writeln!(out)?;
+    writeln!(out, "{0:>12} {1:>7}  awatism", "count", "%")?;
+    for (ip, count) in hot {
+        let awatism = program.awatisms()[ip];
+        writeln!(
+            out,
+            "{count:>12} {0:>6.2}%  [{ip}] {awatism}",
+            percent(*count)
+        )?;
+    }
+
+    let mut regions = profile.regions(program);
+    regions.sort_by(|a, b| b.count.cmp(&a.count).then(a.start.cmp(&b.start)));
+
+    writeln!(out)?;
+    writeln!(out, "{0:>12} {1:>7}  label", "count", "%")?;
+    for region in regions {
+        writeln!(
+            out,
+            "{0:>12} {1:>6.2}%  {2} [{3}..{4}]",
+            region.count,
+            percent(region.count),
+            region_name(program, &region),
+            region.start,
+            region.end
+        )?;
+    }
+    return Ok(());
+}
+
+/// Writes one line per executed awatism as `name;label;awatism count`, the folded stack format
+/// read by flamegraph tools
+pub fn write_folded<W: Write>(
+    out: &mut W,
+    name: &str,
+    program: &Program,
+    profile: &Profile,
+) -> io::Result<()> {
+    for region in profile.regions(program) {
+        for ip in region.start..region.end {
+            let count = profile.counts()[ip];
+            if count == 0 {
+                continue;
+            }
+            // Characters such as blo ';' would break the format, so operands are printed as numbers
+            let awatism = program.awatisms()[ip];
+            let frame = match awatism.operand() {
+                Some(operand) => format!("[{ip}] {0} {operand}", awatism.name()),
+                None => format!("[{ip}] {0}", awatism.name()),
+            };
+            let region = region_name(program, &region);
+            writeln!(out, "{name};{region};{frame} {count}")?;
+        }
+    }
+    return Ok(());
+}
+
+/// Names a region by its label, adding the label name if the program has a source map
+fn region_name(program: &Program, region: &Region) -> String {
+    let lbl = match region.label {
+        None => return String::from("<start>"),
+        Some(lbl) => lbl,
+    };
+    return match program.source().and_then(|source| source.label(lbl)) {
+        Some(name) => format!("lbl {lbl} ({name})"),
+        None => format!("lbl {lbl}"),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use awatistic::{AwaCPU, AwawaError, Profile, Program};
+
+    use super::{print_report, write_folded};
+
+    /// Counts from 0 to 3, running the awatisms after `lbl loop` three times
+    const LOOP: &str = "blo 3\nblo 0\nlbl loop\nblo 1\nadd\nlss\njmp loop\ntrm\n";
+
+    fn profile(program: &Program) -> Profile {
+        let mut cpu = AwaCPU::with_io(program, 0, io::empty(), io::sink());
+        cpu.enable_profile();
+        assert!(matches!(cpu.run(), Err(AwawaError::EndOfProgramError())));
+        return cpu.get_profile().unwrap().clone();
+    }
+
+    #[test]
+    fn folded() {
+        let program = Program::from_awasm("loop.awasm", LOOP.as_bytes()).unwrap();
+        let mut out = vec![];
+        write_folded(&mut out, "loop", &program, &profile(&program)).unwrap();
+        let expected = [
+            "loop;<start>;[0] blo 3 1",
+            "loop;<start>;[1] blo 0 1",
+            "loop;lbl 0 (loop);[2] lbl 0 3",
+            "loop;lbl 0 (loop);[3] blo 1 3",
+            "loop;lbl 0 (loop);[4] 4dd 3",
+            "loop;lbl 0 (loop);[5] lss 3",
+            "loop;lbl 0 (loop);[6] jmp 0 2",
+            "loop;lbl 0 (loop);[7] trm 1",
+        ];
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            expected.map(|l| return format!("{l}\n")).concat()
+        );
+    }
+
+    #[test]
+    fn report() {
+        let program = Program::from_awasm("loop.awasm", LOOP.as_bytes()).unwrap();
+        let mut out = vec![];
+        print_report(&mut out, &program, &profile(&program)).unwrap();
+        let expected = "\
+Awatisms executed: 17
+Max Bubble Abyss size: 3
+Double Bubble allocations: 0
+Bubble Abyss allocations: 1
+
+       count       %  awatism
+           3  17.65%  [2] lbl 0
+           3  17.65%  [3] blo 'W'
+           3  17.65%  [4] 4dd
+           3  17.65%  [5] lss
+           2  11.76%  [6] jmp 0
+           1   5.88%  [0] blo 'w'
+           1   5.88%  [1] blo 'A'
+           1   5.88%  [7] trm
+
+       count       %  label
+          15  88.24%  lbl 0 (loop) [2..8]
+           2  11.76%  <start> [0..2]
+";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+}
@@ -0,0 +1,322 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    io::{self, BufRead, Write},
+};
+
+use crate::{
+    awacpu::{awatalk::disawassemble, Awatism},
+    awassembler::{awassemble_source, print_awatisms},
+    errors::{AwassembleError, AwawaLoadError},
+};
+
+/// A decoded AWA5.0 program, independent of the Awatalk or awasm it was loaded from.
+///
+/// A `Program` can be run by any number of `AwaCPU`s, each with its own input and output.
+/// Its `Display` implementation prints the program as Awatalk. Programs with the same awatisms
+/// are equal, no matter where they were loaded from.
+#[derive(Clone, Debug, Default)]
+pub struct Program {
+    awatisms: Vec<Awatism>,
+    labels: HashMap<u8, usize>,
+    /// `awatisms` lowered for execution
+    code: Vec<Instr>,
+    source: Option<SourceMap>,
+}
+
+/// Maps awatisms back to the awasm they were awassembled from
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    /// The awassembled file followed by the files it includes
+    pub files: Vec<String>,
+    /// Where every awatism came from, by instruction index
+    pub locations: Vec<SourceLocation>,
+    /// Names of the labels that were named in the source
+    pub labels: BTreeMap<u8, String>,
+}
+
+/// The place in the awasm an awatism came from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SourceLocation {
+    /// Index into `SourceMap::files`
+    pub file: usize,
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based character column of the awatism
+    pub column: usize,
+}
+
+/// First line of a debug info file
+const DEBUG_INFO_HEADER: &str = "awatistic-debug-info 1";
+
+impl SourceMap {
+    /// Returns the file the awatism at `ip` came from
+    pub fn file(&self, ip: usize) -> Option<&str> {
+        let location = self.locations.get(ip)?;
+        return self.files.get(location.file).map(String::as_str);
+    }
+
+    /// Returns the source line of the awatism at `ip`
+    pub fn line(&self, ip: usize) -> Option<usize> {
+        return self.locations.get(ip).map(|x| x.line);
+    }
+
+    /// Returns the name of label `lbl` if it was named in the source
+    pub fn label(&self, lbl: u8) -> Option<&str> {
+        return self.labels.get(&lbl).map(String::as_str);
+    }
+
+    /// Writes the source map as a debug info file, which `read` turns back into a source map.
+    ///
+    /// After a header line the file lists the source files as `file PATH`, the label names as
+    /// `label LBL NAME` and the location of every awatism as `IP FILE:LINE:COLUMN`, where `FILE`
+    /// is the index of the source file.
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "{DEBUG_INFO_HEADER}")?;
+        for file in &self.files {
+            writeln!(out, "file {file}")?;
+        }
+        for (lbl, name) in &self.labels {
+            writeln!(out, "label {lbl} {name}")?;
+        }
+        for (ip, location) in self.locations.iter().enumerate() {
+            writeln!(
+                out,
+                "{ip} {0}:{1}:{2}",
+                location.file, location.line, location.column
+            )?;
+        }
+        return Ok(());
+    }
+
+    /// Reads a debug info file written by `write`
+    pub fn read<R: BufRead>(src: R) -> io::Result<SourceMap> {
+        let invalid = |number: usize, msg: &str| -> io::Error {
+            return io::Error::new(io::ErrorKind::InvalidData, format!("line {number}: {msg}"));
+        };
+
+        let mut map = SourceMap::default();
+        for (i, line) in src.lines().enumerate() {
+            let line = line?;
+            let number = i + 1;
+            if number == 1 {
+                if line != DEBUG_INFO_HEADER {
+                    return Err(invalid(number, "not an awatistic debug info file"));
+                }
+                continue;
+            }
+
+            if let Some(file) = line.strip_prefix("file ") {
+                map.files.push(String::from(file));
+            } else if let Some(label) = line.strip_prefix("label ") {
+                let (lbl, name) = label
+                    .split_once(' ')
+                    .and_then(|(lbl, name)| Some((lbl.parse().ok()?, name)))
+                    .ok_or_else(|| invalid(number, "expected 'label LBL NAME'"))?;
+                map.labels.insert(lbl, String::from(name));
+            } else {
+                let location = parse_location(&line, map.locations.len(), map.files.len())
+                    .ok_or_else(|| invalid(number, "expected 'IP FILE:LINE:COLUMN'"))?;
+                map.locations.push(location);
+            }
+        }
+        return Ok(map);
+    }
+}
+
+/// Parses `IP FILE:LINE:COLUMN`, `ip` being the expected instruction index and `files` the
+/// number of source files
+fn parse_location(s: &str, ip: usize, files: usize) -> Option<SourceLocation> {
+    let (index, location) = s.split_once(' ')?;
+    let mut parts = location.split(':').map(|x| x.parse::<usize>().ok());
+    let location = SourceLocation {
+        file: parts.next()??,
+        line: parts.next()??,
+        column: parts.next()??,
+    };
+    if index.parse() != Ok(ip) || location.file >= files || parts.next().is_some() {
+        return None;
+    }
+    return Some(location);
+}
+
+/// An awatism lowered for execution, with jump targets resolved to instruction indices
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Instr {
+    Nop,
+    Prn,
+    Pr1,
+    Red,
+    R3d,
+    Blo(i8),
+    Sbm(u8),
+    Pop,
+    Dpl,
+    Srn(u8),
+    Mrg,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Cnt,
+    Jmp(usize),
+    /// `jmp` to a label the program does not define
+    InvalidJmp(u8),
+    Eql,
+    Lss,
+    Gr8,
+    Trm,
+}
+
+impl Program {
+    /// Decodes a program from Awatalk
+    pub fn from_awatalk(awa: &str) -> Result<Program, AwawaLoadError> {
+        let mut awatisms = vec![];
+        disawassemble(awa.chars(), &mut awatisms)?;
+        return Ok(Self::from_awatisms(awatisms));
+    }
+
+    /// Awassembles a program, errors and the source map refer to the source as `file`.
+    /// Files included by the source are found relative to `file`.
+    pub fn from_awasm<R: BufRead>(file: &str, src: R) -> Result<Program, Box<AwassembleError>> {
+        let mut awatisms = vec![];
+        let source = awassemble_source(file, src, &mut awatisms)?;
+        return Ok(Self::from_awatisms(awatisms).with_source(source));
+    }
+
+    pub fn from_awatisms(awatisms: Vec<Awatism>) -> Program {
+        let mut labels = HashMap::new();
+        for (ip, awatism) in awatisms.iter().enumerate() {
+            if let Awatism::Lbl(lbl) = awatism {
+                labels.insert(*lbl, ip);
+            }
+        }
+        let code = awatisms.iter().map(|a| lower(*a, &labels)).collect();
+        return Self {
+            awatisms,
+            labels,
+            code,
+            source: None,
+        };
+    }
+
+    /// Attaches the source the awatisms came from
+    pub fn with_source(mut self, source: SourceMap) -> Program {
+        self.source = Some(source);
+        return self;
+    }
+
+    /// Returns where the awatisms came from if the program was awassembled
+    pub fn source(&self) -> Option<&SourceMap> {
+        return self.source.as_ref();
+    }
+
+    pub fn awatisms(&self) -> &[Awatism] {
+        return &self.awatisms;
+    }
+
+    /// Formats the awatism at `ip` for diagnostics. With a source map label names and the
+    /// location of the awatism in the awasm are added, e.g. `jmp 0 (loop) at loop.awasm:5:3`.
+    pub fn describe(&self, ip: usize) -> String {
+        let awatism = match self.awatisms.get(ip) {
+            None => return String::new(),
+            Some(awatism) => *awatism,
+        };
+        let source = match &self.source {
+            None => return awatism.to_string(),
+            Some(source) => source,
+        };
+
+        let mut res = awatism.to_string();
+        if let Awatism::Lbl(lbl) | Awatism::Jmp(lbl) = awatism {
+            if let Some(name) = source.label(lbl) {
+                res.push_str(&format!(" ({name})"));
+            }
+        }
+        if let (Some(file), Some(location)) = (source.file(ip), source.locations.get(ip)) {
+            res.push_str(&format!(
+                " at {file}:{0}:{1}",
+                location.line, location.column
+            ));
+        }
+        return res;
+    }
+
+    pub fn len(&self) -> usize {
+        return self.awatisms.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.awatisms.is_empty();
+    }
+
+    /// Returns the instruction index of label `lbl` if the program defines it
+    pub fn get_label(&self, lbl: u8) -> Option<usize> {
+        return self.labels.get(&lbl).copied();
+    }
+
+    pub(crate) fn code(&self) -> &[Instr] {
+        return &self.code;
+    }
+
+    /// Returns the instruction indices execution can continue at after the awatism at `ip`.
+    /// Comparisons continue at the next awatism or skip it, `trm` and jumps to undefined labels
+    /// stop execution. An index past the last awatism ends the program.
+    pub(crate) fn successors(&self, ip: usize) -> Vec<usize> {
+        return match self.awatisms[ip] {
+            Awatism::Trm => vec![],
+            Awatism::Jmp(lbl) => self.get_label(lbl).into_iter().collect(),
+            Awatism::Eql | Awatism::Lss | Awatism::Gr8 => vec![ip + 1, ip + 2],
+            _ => vec![ip + 1],
+        };
+    }
+}
+
+fn lower(awatism: Awatism, labels: &HashMap<u8, usize>) -> Instr {
+    return match awatism {
+        Awatism::Nop | Awatism::Lbl(_) => Instr::Nop,
+        Awatism::Prn => Instr::Prn,
+        Awatism::Pr1 => Instr::Pr1,
+        Awatism::Red => Instr::Red,
+        Awatism::R3d => Instr::R3d,
+        Awatism::Blo(val) => Instr::Blo(val),
+        Awatism::Sbm(val) => Instr::Sbm(val),
+        Awatism::Pop => Instr::Pop,
+        Awatism::Dpl => Instr::Dpl,
+        Awatism::Srn(val) => Instr::Srn(val),
+        Awatism::Mrg => Instr::Mrg,
+        Awatism::Add => Instr::Add,
+        Awatism::Sub => Instr::Sub,
+        Awatism::Mul => Instr::Mul,
+        Awatism::Div => Instr::Div,
+        Awatism::Cnt => Instr::Cnt,
+        Awatism::Jmp(lbl) => match labels.get(&lbl) {
+            Some(target) => Instr::Jmp(*target),
+            None => Instr::InvalidJmp(lbl),
+        },
+        Awatism::Eql => Instr::Eql,
+        Awatism::Lss => Instr::Lss,
+        Awatism::Gr8 => Instr::Gr8,
+        Awatism::Trm => Instr::Trm,
+    };
+}
+
+impl PartialEq for Program {
+    fn eq(&self, other: &Self) -> bool {
+        return self.awatisms == other.awatisms;
+    }
+}
+
+impl Eq for Program {}
+
+impl From<Vec<Awatism>> for Program {
+    fn from(awatisms: Vec<Awatism>) -> Self {
+        return Self::from_awatisms(awatisms);
+    }
+}
+
+impl Display for Program {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return print_awatisms(self.awatisms.iter().copied(), f);
+    }
+}
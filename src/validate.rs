@@ -0,0 +1,276 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    str::FromStr,
+};
+
+use crate::{awacpu::Awatism, program::Program};
+
+/// A problem with the labels or control flow of a program, found by `validate`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Finding {
+    /// `jmp` at `ip` to a label the program does not define
+    UndefinedLabel { ip: usize, lbl: u8 },
+    /// `lbl` defined at `first` and again at `second`, jumps go to the last definition
+    DuplicateLabel {
+        lbl: u8,
+        first: usize,
+        second: usize,
+    },
+    /// Awatisms `start..end` can never be executed
+    Unreachable { start: usize, end: usize },
+    /// `lbl` at `ip` that no `jmp` jumps to
+    UnusedLabel { ip: usize, lbl: u8 },
+}
+
+/// The kinds of `Finding`, each reported at its own `Level`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Category {
+    UndefinedLabel,
+    DuplicateLabel,
+    Unreachable,
+    UnusedLabel,
+}
+
+/// How a category of findings is treated
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    /// Not reported
+    Allow,
+    /// Reported, the program can still run
+    Warn,
+    /// Reported, the program must not run
+    Deny,
+}
+
+/// The level of every category of findings
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Levels {
+    pub undefined_label: Level,
+    pub duplicate_label: Level,
+    pub unreachable: Level,
+    pub unused_label: Level,
+}
+
+impl Default for Levels {
+    fn default() -> Self {
+        return Self {
+            undefined_label: Level::Warn,
+            duplicate_label: Level::Warn,
+            unreachable: Level::Warn,
+            unused_label: Level::Warn,
+        };
+    }
+}
+
+impl Levels {
+    pub fn get(&self, category: Category) -> Level {
+        return match category {
+            Category::UndefinedLabel => self.undefined_label,
+            Category::DuplicateLabel => self.duplicate_label,
+            Category::Unreachable => self.unreachable,
+            Category::UnusedLabel => self.unused_label,
+        };
+    }
+
+    pub fn set(&mut self, category: Category, level: Level) {
+        match category {
+            Category::UndefinedLabel => self.undefined_label = level,
+            Category::DuplicateLabel => self.duplicate_label = level,
+            Category::Unreachable => self.unreachable = level,
+            Category::UnusedLabel => self.unused_label = level,
+        }
+    }
+}
+
+impl Finding {
+    pub fn category(&self) -> Category {
+        return match self {
+            Self::UndefinedLabel { .. } => Category::UndefinedLabel,
+            Self::DuplicateLabel { .. } => Category::DuplicateLabel,
+            Self::Unreachable { .. } => Category::Unreachable,
+            Self::UnusedLabel { .. } => Category::UnusedLabel,
+        };
+    }
+
+    /// Instruction index the finding points at
+    pub fn ip(&self) -> usize {
+        return match self {
+            Self::UndefinedLabel { ip, .. } | Self::UnusedLabel { ip, .. } => *ip,
+            Self::DuplicateLabel { second, .. } => *second,
+            Self::Unreachable { start, .. } => *start,
+        };
+    }
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            Self::UndefinedLabel { ip, lbl } => {
+                write!(f, "[{ip}] jmp {lbl} jumps to undefined label {lbl}")
+            }
+            Self::DuplicateLabel { lbl, first, second } => write!(
+                f,
+                "[{second}] lbl {lbl} is already defined at [{first}], jumps go to [{second}]"
+            ),
+            Self::Unreachable { start, end } if end - start == 1 => {
+                write!(f, "[{start}] is never reached")
+            }
+            Self::Unreachable { start, end } => {
+                write!(f, "[{start}] to [{0}] are never reached", end - 1)
+            }
+            Self::UnusedLabel { ip, lbl } => write!(f, "[{ip}] lbl {lbl} is never jumped to"),
+        };
+    }
+}
+
+impl Display for Category {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            Self::UndefinedLabel => write!(f, "undefined-label"),
+            Self::DuplicateLabel => write!(f, "duplicate-label"),
+            Self::Unreachable => write!(f, "unreachable"),
+            Self::UnusedLabel => write!(f, "unused-label"),
+        };
+    }
+}
+
+impl FromStr for Category {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "undefined-label" => Ok(Self::UndefinedLabel),
+            "duplicate-label" => Ok(Self::DuplicateLabel),
+            "unreachable" => Ok(Self::Unreachable),
+            "unused-label" => Ok(Self::UnusedLabel),
+            _ => Err(format!("unknown category '{s}'")),
+        };
+    }
+}
+
+/// Finds jumps to undefined labels, labels defined more than once, awatisms that can never be
+/// executed and labels that are never jumped to
+pub fn validate(program: &Program) -> Vec<Finding> {
+    let awatisms = program.awatisms();
+    let mut findings = vec![];
+
+    let mut defined: HashMap<u8, usize> = HashMap::new();
+    let mut jumped = HashSet::new();
+    for (ip, awatism) in awatisms.iter().enumerate() {
+        match awatism {
+            Awatism::Lbl(lbl) => {
+                if let Some(first) = defined.insert(*lbl, ip) {
+                    findings.push(Finding::DuplicateLabel {
+                        lbl: *lbl,
+                        first,
+                        second: ip,
+                    });
+                }
+            }
+            Awatism::Jmp(lbl) => {
+                jumped.insert(*lbl);
+                if program.get_label(*lbl).is_none() {
+                    findings.push(Finding::UndefinedLabel { ip, lbl: *lbl });
+                }
+            }
+            _ => (),
+        }
+    }
+
+    for (ip, awatism) in awatisms.iter().enumerate() {
+        if let Awatism::Lbl(lbl) = awatism {
+            if !jumped.contains(lbl) {
+                findings.push(Finding::UnusedLabel { ip, lbl: *lbl });
+            }
+        }
+    }
+
+    let mut reached = vec![false; awatisms.len()];
+    let mut pending = vec![0];
+    while let Some(ip) = pending.pop() {
+        if ip >= awatisms.len() || reached[ip] {
+            continue;
+        }
+        reached[ip] = true;
+        pending.extend(program.successors(ip));
+    }
+
+    let mut start = None;
+    for ip in 0..=awatisms.len() {
+        match (start, reached.get(ip).copied().unwrap_or(true)) {
+            (None, false) => start = Some(ip),
+            (Some(s), true) => {
+                findings.push(Finding::Unreachable { start: s, end: ip });
+                start = None;
+            }
+            _ => (),
+        }
+    }
+
+    findings.sort_by_key(|finding| finding.ip());
+    return findings;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate, Category, Finding, Level, Levels};
+    use crate::program::Program;
+
+    fn validate_awasm(src: &str) -> Vec<Finding> {
+        let program = Program::from_awasm("validate.awasm", src.as_bytes()).unwrap();
+        return validate(&program);
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(validate_awasm("lbl 0\nblo 1\njmp 0\n"), []);
+
+        // jmp 0 goes to the second lbl 0, skipping jmp 3
+        assert_eq!(
+            validate_awasm("lbl 0\njmp 0\njmp 3\nlbl 0\n"),
+            [
+                Finding::UndefinedLabel { ip: 2, lbl: 3 },
+                Finding::Unreachable { start: 2, end: 3 },
+                Finding::DuplicateLabel {
+                    lbl: 0,
+                    first: 0,
+                    second: 3,
+                },
+            ]
+        );
+
+        assert_eq!(
+            validate_awasm("lbl 1\nblo 1\nlbl 2\njmp 2\n"),
+            [Finding::UnusedLabel { ip: 0, lbl: 1 }]
+        );
+    }
+
+    #[test]
+    fn unreachable() {
+        assert_eq!(
+            validate_awasm("trm\nblo 1\nprn\nlbl 0\nprn\n"),
+            [
+                Finding::Unreachable { start: 1, end: 5 },
+                Finding::UnusedLabel { ip: 3, lbl: 0 },
+            ]
+        );
+
+        // Comparisons may skip a jump back into the loop
+        let src = "blo 1\nlbl 0\nblo 1\neql\njmp 0\nprn\njmp 1\nprn\nlbl 1\n";
+        assert_eq!(
+            validate_awasm(src),
+            [Finding::Unreachable { start: 7, end: 8 }]
+        );
+    }
+
+    #[test]
+    fn levels() {
+        let mut levels = Levels::default();
+        assert_eq!(levels.get(Category::UndefinedLabel), Level::Warn);
+        levels.set(Category::UndefinedLabel, Level::Allow);
+        assert_eq!(levels.undefined_label, Level::Allow);
+        assert_eq!("unused-label".parse(), Ok(Category::UnusedLabel));
+        assert!("unused".parse::<Category>().is_err());
+    }
+}
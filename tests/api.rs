@@ -0,0 +1,567 @@
+#![allow(clippy::needless_return)]
+
+use awatistic::{
+    awassemble, disawassemble, print_awatism, print_awatisms, AwaCPU, AwassembleErrorKind, Awatism,
+    AwawaError, AwawaLoadError, Limits, Outcomes, Program, Region, SourceLocation, SourceMap,
+    StepEvent,
+};
+use std::{
+    cell::RefCell,
+    fs::File,
+    io::{self, BufReader, Read, Write},
+    rc::Rc,
+    time::Duration,
+};
+
+fn awassemble_str(src: &str) -> String {
+    let mut awatisms = vec![];
+    awassemble(src.as_bytes(), &mut awatisms).unwrap();
+
+    let mut awa = String::new();
+    print_awatisms(awatisms, &mut awa).unwrap();
+    return awa;
+}
+
+#[test]
+fn awassemble_example() {
+    let src = include_str!("../examples/loop.awasm");
+    let awa = include_str!("../examples/loop.awa");
+
+    assert_eq!(awassemble_str(src), awa.trim_end());
+}
+
+#[test]
+fn disawassemble_roundtrip() {
+    let awa = include_str!("../examples/jeLLy.awa");
+
+    let mut awatisms = vec![];
+    disawassemble(awa.chars(), &mut awatisms).unwrap();
+
+    assert_eq!(awatisms.len(), 12);
+    assert_eq!(awatisms[0], Awatism::Blo(52));
+    assert_eq!(awatisms[6], Awatism::Srn(6));
+    assert_eq!(awatisms[11], Awatism::Prn);
+
+    let mut out = String::new();
+    print_awatisms(awatisms.clone(), &mut out).unwrap();
+
+    let mut roundtrip = vec![];
+    disawassemble(out.chars(), &mut roundtrip).unwrap();
+    assert_eq!(roundtrip, awatisms);
+}
+
+#[test]
+fn load_and_run() {
+    let awa = awassemble_str("blo 5\nblo 0\nlbl 1\nblo 1\nadd\nlss\njmp 1\ntrm\n");
+
+    let program = Program::from_awatalk(&awa).unwrap();
+    assert_eq!(program.len(), 8);
+    let mut cpu = AwaCPU::new(&program, 0);
+
+    match cpu.run() {
+        Err(AwawaError::EndOfProgramError()) => (),
+        res => panic!("unexpected result {res:?}"),
+    }
+    assert_eq!(cpu.get_ip(), 7);
+    assert_eq!(format!("{}", cpu.get_bubble_abyss()), "[2] 'E', 'E'");
+}
+
+#[test]
+fn runtime_error() {
+    let awa = awassemble_str("blo 1\npop\npop\n");
+
+    let program = Program::from_awatalk(&awa).unwrap();
+    let mut cpu = AwaCPU::new(&program, 0);
+
+    match cpu.run() {
+        Err(AwawaError::BubbleAbyssEmpty) => (),
+        res => panic!("unexpected result {res:?}"),
+    }
+    assert_eq!(cpu.get_ip(), 2);
+}
+
+#[test]
+fn load_errors() {
+    fn load_error(awa: &str) -> AwawaLoadError {
+        return Program::from_awatalk(awa).unwrap_err();
+    }
+
+    assert!(matches!(
+        load_error("wa"),
+        AwawaLoadError::MissingInitialAwaError(_)
+    ));
+    assert!(matches!(
+        load_error("awa awa aw"),
+        AwawaLoadError::AwawaParseError(_)
+    ));
+    assert!(matches!(
+        load_error("awa awa awawa awawa"),
+        AwawaLoadError::MalformedAwatismError(_)
+    ));
+    assert!(matches!(
+        load_error("awa wawawawa awa"),
+        AwawaLoadError::UnknownAwatismError(0x1E, _)
+    ));
+
+    // Awatalk ending inside an awatism code ends the program, inside an argument it is malformed
+    assert_eq!(Program::from_awatalk("awa awa awawa").unwrap().len(), 0);
+    assert!(matches!(
+        load_error("awa awa awa wa awa wa awa"),
+        AwawaLoadError::MalformedAwatismError(_)
+    ));
+
+    let span = |awa| *load_error(awa).span();
+    let s = span("  wa");
+    assert_eq!((s.offset, s.len), (2, 2));
+    let s = span("awa awa\nawa aw");
+    assert_eq!(
+        (s.index, s.offset, s.line, s.column, s.len),
+        (0, 12, 2, 5, 2)
+    );
+    let s = span("awa awa awawa awawa");
+    assert_eq!((s.index, s.offset, s.len), (0, 4, 15));
+
+    let e = load_error("awa awa awa awa awa awa wawawawa awa");
+    assert_eq!((e.span().index, e.span().offset, e.span().len), (1, 24, 12));
+    assert_eq!(format!("{e}"), "Awatism 30 not implemented");
+}
+
+#[test]
+fn run_with_io() {
+    let awa = awassemble_str("r3d\nblo 2\nmul\npr1\nred\nprn\n");
+
+    let mut out = vec![];
+    {
+        let program = Program::from_awatalk(&awa).unwrap();
+        let mut cpu = AwaCPU::new(&program, 0);
+        match cpu.run_with_io("21\nJelly\n".as_bytes(), &mut out) {
+            Err(AwawaError::EndOfProgramError()) => (),
+            res => panic!("unexpected result {res:?}"),
+        }
+    }
+    assert_eq!(String::from_utf8(out).unwrap(), "42 Jelly\n");
+}
+
+#[test]
+fn step_events() {
+    let awa = awassemble_str("blo 1\nblo 2\nlss\nnop\nprn\ntrm\n");
+
+    let program = Program::from_awatalk(&awa).unwrap();
+    let mut cpu = AwaCPU::with_io(&program, 0, "".as_bytes(), vec![]);
+
+    cpu.step().unwrap();
+    assert_eq!(
+        cpu.step().unwrap(),
+        StepEvent::Executed {
+            awatism: Awatism::Blo(2),
+            ip_before: 1,
+            ip_after: 2,
+            comparison: None,
+            output: None,
+        }
+    );
+    assert_eq!(
+        cpu.step().unwrap(),
+        StepEvent::Executed {
+            awatism: Awatism::Lss,
+            ip_before: 2,
+            ip_after: 4,
+            comparison: Some(false),
+            output: None,
+        }
+    );
+    assert_eq!(
+        cpu.step().unwrap(),
+        StepEvent::Executed {
+            awatism: Awatism::Prn,
+            ip_before: 4,
+            ip_after: 5,
+            comparison: None,
+            output: Some(String::from("a")),
+        }
+    );
+    assert_eq!(cpu.step().unwrap(), StepEvent::Halted { ip: 5 });
+    assert_eq!(cpu.step().unwrap(), StepEvent::Halted { ip: 5 });
+}
+
+#[test]
+fn limits() {
+    fn run_limited(src: &str, limits: Limits) -> AwawaError {
+        let awa = awassemble_str(src);
+        let program = Program::from_awatalk(&awa).unwrap();
+        let mut cpu = AwaCPU::new(&program, 0);
+        cpu.set_limits(limits);
+        return cpu.run().unwrap_err();
+    }
+
+    let endless = "lbl 0\njmp 0\n";
+    let limits = Limits {
+        max_steps: Some(100),
+        ..Default::default()
+    };
+    assert!(matches!(
+        run_limited(endless, limits),
+        AwawaError::FuelExhaustedError(100)
+    ));
+
+    let limits = Limits {
+        timeout: Some(Duration::from_millis(10)),
+        ..Default::default()
+    };
+    assert!(matches!(
+        run_limited(endless, limits),
+        AwawaError::TimeoutError(_)
+    ));
+
+    let limits = Limits {
+        max_abyss_size: Some(10),
+        ..Default::default()
+    };
+    assert!(matches!(
+        run_limited("lbl 0\nblo 1\njmp 0\n", limits),
+        AwawaError::BubbleAbyssTooLargeError(10)
+    ));
+
+    let limits = Limits {
+        max_depth: Some(3),
+        ..Default::default()
+    };
+    assert!(matches!(
+        run_limited("blo 1\nlbl 0\nsrn 1\njmp 0\n", limits),
+        AwawaError::BubbleNestingTooDeepError(3)
+    ));
+}
+
+#[test]
+fn program_reuse() {
+    let src = "red\nprn\nr3d\nblo 1\nadd\npr1\n";
+    let program = Program::from_awasm("reuse.awasm", src.as_bytes()).unwrap();
+    assert_eq!(program.len(), 6);
+
+    // Display prints the program as Awatalk
+    let awa = program.to_string();
+    assert_eq!(awa, awassemble_str(src));
+    assert_eq!(Program::from_awatalk(&awa).unwrap(), program);
+
+    let mut outputs = vec![];
+    for input in ["Jelly\n41\n", "Awa\n1\n"] {
+        let mut out = vec![];
+        {
+            let mut cpu = AwaCPU::with_io(&program, 0, input.as_bytes(), &mut out);
+            cpu.run().unwrap_err();
+        }
+        outputs.push(String::from_utf8(out).unwrap());
+    }
+    assert_eq!(outputs, ["Jelly\n42 ", "Awa\n2 "]);
+}
+
+#[test]
+fn run_matches_step() {
+    let awa = include_str!("../examples/loop.awa");
+    let program = Program::from_awatalk(awa).unwrap();
+
+    let mut run_out = vec![];
+    let mut step_out = vec![];
+    let (run_ip, step_ip) = {
+        let mut cpu = AwaCPU::with_io(&program, 0, "".as_bytes(), &mut run_out);
+        assert!(matches!(cpu.run(), Err(AwawaError::EndOfProgramError())));
+        let run_ip = cpu.get_ip();
+
+        let mut cpu = AwaCPU::with_io(&program, 0, "".as_bytes(), &mut step_out);
+        while let StepEvent::Executed { .. } = cpu.step().unwrap() {}
+        (run_ip, cpu.get_ip())
+    };
+    assert_eq!(run_ip, step_ip);
+    assert_eq!(run_out, step_out);
+
+    // Undefined labels are only an error once the jmp is executed
+    let program = Program::from_awasm("jmp.awasm", "nop\njmp 3\n".as_bytes()).unwrap();
+    let mut cpu = AwaCPU::new(&program, 0);
+    assert!(matches!(cpu.run(), Err(AwawaError::InvalidLabelError(3))));
+    assert_eq!(cpu.get_ip(), 1);
+}
+
+#[test]
+fn buffered_output() {
+    type Writes = Rc<RefCell<Vec<String>>>;
+
+    /// Records every write to see how the output was chunked
+    struct Recorder(Writes);
+
+    impl Write for Recorder {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let s = String::from_utf8_lossy(buf).into_owned();
+            self.0.borrow_mut().push(s);
+            return Ok(buf.len());
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            return Ok(());
+        }
+    }
+
+    /// Input that records what had been written when it is read
+    struct Prompted(Writes, Writes);
+
+    impl Read for Prompted {
+        fn read(&mut self, mut buf: &mut [u8]) -> io::Result<usize> {
+            *self.1.borrow_mut() = self.0.borrow().clone();
+            return buf.write(b"Jelly\n");
+        }
+    }
+
+    let src = "blo \"awa\"\nsrn 3\nprn\nblo 1\nprn\nred\nprn\n";
+    let program = Program::from_awasm("prompt.awasm", src.as_bytes()).unwrap();
+
+    let writes = Writes::default();
+    let seen = Writes::default();
+    {
+        let input = BufReader::new(Prompted(writes.clone(), seen.clone()));
+        let mut cpu = AwaCPU::with_io(&program, 0, input, Recorder(writes.clone()));
+        assert!(matches!(cpu.run(), Err(AwawaError::EndOfProgramError())));
+    }
+
+    // Both prn before red arrive in one write, before the input is read
+    assert_eq!(*seen.borrow(), ["awaW"]);
+    assert_eq!(*writes.borrow(), ["awaW", "Jelly\n"]);
+}
+
+#[test]
+fn trace_json() {
+    let src = "blo 1\nblo 'A'\nsrn 2\ncnt\nblo 2\neql\npr1\npop\nprn\nsub\n";
+    let program = Program::from_awasm("trace.awasm", src.as_bytes()).unwrap();
+
+    let mut trace = vec![];
+    let mut out = vec![];
+    {
+        let mut cpu = AwaCPU::with_io(&program, 0, io::empty(), &mut out);
+        cpu.set_trace(&mut trace);
+        assert!(matches!(cpu.run(), Err(AwawaError::BubbleAbyssEmpty)));
+    }
+    assert_eq!(out, b"2 AW");
+
+    let trace = String::from_utf8(trace).unwrap();
+    let lines: Vec<_> = trace.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            r#"{"step":1,"ip":0,"awatism":"blo","operand":1,"next":1,"abyss":[1]}"#,
+            r#"{"step":2,"ip":1,"awatism":"blo","operand":0,"next":2,"abyss":[0,1]}"#,
+            r#"{"step":3,"ip":2,"awatism":"srn","operand":2,"next":3,"abyss":[[0,1]]}"#,
+            r#"{"step":4,"ip":3,"awatism":"cnt","next":4,"abyss":[2,[0,1]]}"#,
+            r#"{"step":5,"ip":4,"awatism":"blo","operand":2,"next":5,"abyss":[2,2,[0,1]]}"#,
+            r#"{"step":6,"ip":5,"awatism":"eql","next":6,"comparison":true,"abyss":[2,2,[0,1]]}"#,
+            r#"{"step":7,"ip":6,"awatism":"pr1","next":7,"output":"2 ","abyss":[2,[0,1]]}"#,
+            r#"{"step":8,"ip":7,"awatism":"pop","next":8,"abyss":[[0,1]]}"#,
+            r#"{"step":9,"ip":8,"awatism":"prn","next":9,"output":"AW","abyss":[]}"#,
+            r#"{"step":10,"ip":9,"error":"Bubble Abyss accessed but it is empty"}"#,
+        ]
+    );
+
+    // A trace that cannot be written does not hide the error of the step it records
+    struct Broken;
+
+    impl Write for Broken {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            return Err(io::Error::other("broken"));
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            return Ok(());
+        }
+    }
+
+    let program = Program::from_awasm("r3d.awasm", "r3d\n".as_bytes()).unwrap();
+    // The error line is longer than the trace's buffer, so recording it fails right away
+    let input = "9".repeat(10_000);
+    let mut cpu = AwaCPU::with_io(&program, 0, input.as_bytes(), io::sink());
+    cpu.set_trace(Broken);
+    assert!(matches!(cpu.step(), Err(AwawaError::NotANumberError(_))));
+}
+
+#[test]
+fn profile() {
+    let src = "blo 0\nblo 3\nlbl 0\nblo 1\nsbm 1\nsub\ngr8\njmp 0\nlbl 1\npop\n";
+    let program = Program::from_awasm("countdown.awasm", src.as_bytes()).unwrap();
+
+    let mut cpu = AwaCPU::with_io(&program, 0, io::empty(), io::sink());
+    assert!(cpu.get_profile().is_none());
+    cpu.enable_profile();
+    assert!(matches!(cpu.run(), Err(AwawaError::EndOfProgramError())));
+
+    let profile = cpu.get_profile().unwrap();
+    assert_eq!(profile.counts(), [1, 1, 3, 3, 3, 3, 3, 2, 1, 1]);
+    assert_eq!(profile.total(), 21);
+    assert_eq!(profile.total(), cpu.get_steps());
+    assert_eq!(profile.max_abyss_size(), 3);
+    assert_eq!(
+        profile.regions(&program),
+        [
+            Region {
+                label: None,
+                start: 0,
+                end: 2,
+                count: 2
+            },
+            Region {
+                label: Some(0),
+                start: 2,
+                end: 8,
+                count: 17
+            },
+            Region {
+                label: Some(1),
+                start: 8,
+                end: 10,
+                count: 2
+            },
+        ]
+    );
+    assert_eq!(profile.double_bubble_allocations(), 0);
+    assert_eq!(profile.abyss_allocations(), 1);
+
+    // dpl shares the Double Bubble made by srn until mrg modifies it
+    let src = "blo 1\nblo 2\nsrn 2\ndpl\nblo 3\nmrg\n";
+    let program = Program::from_awasm("merge.awasm", src.as_bytes()).unwrap();
+    let mut cpu = AwaCPU::with_io(&program, 0, io::empty(), io::sink());
+    cpu.enable_profile();
+    assert!(matches!(cpu.run(), Err(AwawaError::EndOfProgramError())));
+    assert_eq!(cpu.get_profile().unwrap().double_bubble_allocations(), 2);
+}
+
+#[test]
+fn coverage() {
+    let src = "# countdown\nblo 0\nblo 2\n\nlbl 0\n  blo 1\n  sbm 1\n  sub\n  gr8\n  jmp 0\nblo \"wa\"\neql\ntrm\n";
+    let program = Program::from_awasm("countdown.awasm", src.as_bytes()).unwrap();
+    assert!(Program::from_awatalk(&program.to_string())
+        .unwrap()
+        .source()
+        .is_none());
+
+    let source = program.source().unwrap();
+    assert_eq!(source.files, ["countdown.awasm"]);
+    let lines: Vec<usize> = source.locations.iter().map(|x| x.line).collect();
+    assert_eq!(lines, [2, 3, 5, 6, 7, 8, 9, 10, 11, 11, 12, 13]);
+    assert_eq!(source.line(9), Some(11));
+    assert_eq!(source.file(9), Some("countdown.awasm"));
+
+    let mut cpu = AwaCPU::with_io(&program, 0, io::empty(), io::sink());
+    cpu.enable_profile();
+    assert!(matches!(cpu.run(), Err(AwawaError::EndOfProgramError())));
+
+    let profile = cpu.get_profile().unwrap();
+    let outcomes = profile.outcomes();
+    assert_eq!(
+        outcomes[6],
+        Outcomes {
+            was_true: 1,
+            was_false: 1
+        }
+    );
+    assert_eq!(
+        outcomes[10],
+        Outcomes {
+            was_true: 0,
+            was_false: 1
+        }
+    );
+    assert_eq!(outcomes[5], Outcomes::default());
+    assert_eq!(profile.counts()[11], 0);
+}
+
+#[test]
+fn include() {
+    let file = "tests/include/main.awasm";
+    let src = BufReader::new(File::open(file).unwrap());
+    let program = Program::from_awasm(file, src).unwrap();
+
+    let source = program.source().unwrap();
+    assert_eq!(source.files, [file, "tests/include/lib/print.awasm"]);
+    assert_eq!(
+        source.locations[0],
+        SourceLocation {
+            file: 1,
+            line: 3,
+            column: 5
+        }
+    );
+    assert_eq!(source.file(3), Some("tests/include/lib/print.awasm"));
+
+    let mut out = vec![];
+    let mut cpu = AwaCPU::with_io(&program, 0, io::empty(), &mut out);
+    assert!(matches!(cpu.run(), Err(AwawaError::EndOfProgramError())));
+    drop(cpu);
+    assert_eq!(String::from_utf8(out).unwrap(), "aw");
+
+    // lib/cycle.awasm includes cycle.awasm again
+    let file = "tests/include/cycle.awasm";
+    let src = BufReader::new(File::open(file).unwrap());
+    let e = Program::from_awasm(file, src).unwrap_err();
+    assert!(matches!(e.kind, AwassembleErrorKind::IncludeCycleError(_)));
+    assert_eq!(
+        (e.file.as_str(), e.line),
+        ("tests/include/lib/cycle.awasm", 2)
+    );
+    assert_eq!(e.notes, ["included from tests/include/cycle.awasm:1:1"]);
+
+    let e = Program::from_awasm(file, ".include \"missing.awasm\"".as_bytes()).unwrap_err();
+    assert!(
+        matches!(e.kind, AwassembleErrorKind::IncludeError(path, _) if path == "tests/include/missing.awasm")
+    );
+
+    // An included file that is not UTF-8 is reported instead of being cut short
+    let src = ".include \"lib/latin1.awasm\"";
+    let e = Program::from_awasm(file, src.as_bytes()).unwrap_err();
+    assert!(
+        matches!(&e.kind, AwassembleErrorKind::ReadError(path, _) if path == "tests/include/lib/latin1.awasm")
+    );
+    assert_eq!(
+        (e.file.as_str(), e.line),
+        ("tests/include/lib/latin1.awasm", 2)
+    );
+    assert_eq!(e.notes, ["included from tests/include/cycle.awasm:1:1"]);
+}
+
+#[test]
+fn awatalk_fragments() {
+    let program = Program::from_awasm("blo.awasm", "blo \"Aw\"\nsrn 2\nprn\n".as_bytes()).unwrap();
+    let mut fragments = String::from("awa");
+    for awatism in program.awatisms() {
+        print_awatism(*awatism, &mut fragments).unwrap();
+    }
+    assert_eq!(fragments, program.to_string());
+
+    let mut fragment = String::new();
+    print_awatism(Awatism::Srn(2), &mut fragment).unwrap();
+    assert_eq!(fragment, " awawa awa awawa awa awa awawa awa");
+}
+
+#[test]
+fn debug_info() {
+    let src = "blo 1\nlbl loop\n  jmp loop\nlbl 7\n";
+    let program = Program::from_awasm("loop.awasm", src.as_bytes()).unwrap();
+    assert_eq!(program.describe(2), "jmp 0 (loop) at loop.awasm:3:3");
+    assert_eq!(program.describe(3), "lbl 7 at loop.awasm:4:1");
+
+    let mut out = vec![];
+    program.source().unwrap().write(&mut out).unwrap();
+    let debug_info = String::from_utf8(out).unwrap();
+    assert_eq!(
+        debug_info,
+        "awatistic-debug-info 1\nfile loop.awasm\nlabel 0 loop\n0 0:1:1\n1 0:2:1\n2 0:3:3\n3 0:4:1\n"
+    );
+
+    // Debug info brings label names and source locations back to a program loaded from Awatalk
+    let source = SourceMap::read(debug_info.as_bytes()).unwrap();
+    assert_eq!(Some(&source), program.source());
+    let awatalk = Program::from_awatalk(&program.to_string()).unwrap();
+    assert_eq!(awatalk.describe(2), "jmp 0");
+    assert_eq!(
+        awatalk.with_source(source).describe(2),
+        "jmp 0 (loop) at loop.awasm:3:3"
+    );
+
+    assert!(SourceMap::read("awa\n".as_bytes()).is_err());
+    assert!(SourceMap::read("awatistic-debug-info 1\nfile a\n1 0:1:1\n".as_bytes()).is_err());
+    assert!(SourceMap::read("awatistic-debug-info 1\n0 0:1:1\n".as_bytes()).is_err());
+}